@@ -17,5 +17,11 @@ impl AquariumService {
         let ids = self.repository.list_aquarium_ids().await?;
         Ok(ids.into_iter().map(Aquarium::new).collect())
     }
+
+    /// Whether `id` is one of the aquariums reported by the repository
+    pub async fn is_known(&self, id: &str) -> anyhow::Result<bool> {
+        let ids = self.repository.list_aquarium_ids().await?;
+        Ok(ids.iter().any(|known| known == id))
+    }
 }
 
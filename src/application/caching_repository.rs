@@ -0,0 +1,469 @@
+// Caching repository - TTL cache in front of any TelemetryRepository
+use crate::application::telemetry_repository::{SeriesMetadata, TelemetryRepository};
+use crate::domain::downsampling::Downsampling;
+use crate::domain::telemetry::{TaggedSeries, TimeSeriesPoint};
+use crate::domain::time_range::TimeRange;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Time-to-live per repository method; a zero TTL disables caching for that method
+#[derive(Debug, Clone, Copy)]
+pub struct CacheTtls {
+    pub single_value: Duration,
+    pub time_series: Duration,
+    pub series_metadata: Duration,
+    pub aquarium_ids: Duration,
+}
+
+/// Snapshot of cache counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    AquariumIds,
+    SeriesMetadata { aquarium_id: String, range: TimeRange },
+    SingleValue(String),
+    TimeSeries {
+        query: String,
+        max_points: usize,
+        downsampling: Downsampling,
+    },
+    TaggedSeries {
+        query: String,
+        max_points: usize,
+        downsampling: Downsampling,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum CachedValue {
+    AquariumIds(Vec<String>),
+    SeriesMetadata(Vec<SeriesMetadata>),
+    SingleValue(Option<f64>),
+    TimeSeries(Vec<TimeSeriesPoint>),
+    TaggedSeries(Vec<TaggedSeries>),
+}
+
+struct CacheEntry {
+    value: CachedValue,
+    expires_at: Instant,
+    last_used: Instant,
+    bytes: usize,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    bytes: usize,
+}
+
+/// Wraps a `TelemetryRepository` and caches successful results keyed on the
+/// prepared query, within a memory budget. Errors are never cached.
+pub struct CachingRepository {
+    inner: Arc<dyn TelemetryRepository>,
+    /// Entries and counters, shared with the views made by `with_inner`
+    store: Arc<CacheStore>,
+}
+
+struct CacheStore {
+    ttls: CacheTtls,
+    max_bytes: usize,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CachingRepository {
+    pub fn new(inner: Arc<dyn TelemetryRepository>, ttls: CacheTtls, max_bytes: usize) -> Self {
+        Self {
+            inner,
+            store: Arc::new(CacheStore {
+                ttls,
+                max_bytes,
+                state: Mutex::new(CacheState::default()),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// The repository misses are sent to
+    pub fn inner(&self) -> Arc<dyn TelemetryRepository> {
+        self.inner.clone()
+    }
+
+    /// The same cache in front of another repository, e.g. a dashboard stream's
+    /// scheduled view of `inner`, so only misses wait for a query permit
+    pub fn with_inner(&self, inner: Arc<dyn TelemetryRepository>) -> Self {
+        Self {
+            inner,
+            store: self.store.clone(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state();
+        CacheStats {
+            hits: self.store.hits.load(Ordering::Relaxed),
+            misses: self.store.misses.load(Ordering::Relaxed),
+            entries: state.entries.len(),
+            bytes: state.bytes,
+        }
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<CachedValue> {
+        let now = Instant::now();
+        let mut state = self.state();
+
+        let value = match state.entries.get_mut(key) {
+            Some(entry) if entry.expires_at > now => {
+                entry.last_used = now;
+                Some(entry.value.clone())
+            }
+            Some(_) => {
+                if let Some(entry) = state.entries.remove(key) {
+                    state.bytes -= entry.bytes;
+                }
+                None
+            }
+            None => None,
+        };
+
+        let counter = if value.is_some() { &self.store.hits } else { &self.store.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    fn put(&self, key: CacheKey, value: CachedValue, ttl: Duration) {
+        let bytes = estimate_size(&key, &value);
+        if ttl.is_zero() || bytes > self.store.max_bytes {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut state = self.state();
+        if let Some(old) = state.entries.remove(&key) {
+            state.bytes -= old.bytes;
+        }
+
+        // Make room: drop expired entries first, then the least recently used
+        if state.bytes + bytes > self.store.max_bytes {
+            state.entries.retain(|_, entry| entry.expires_at > now);
+            state.bytes = state.entries.values().map(|entry| entry.bytes).sum();
+        }
+        while state.bytes + bytes > self.store.max_bytes {
+            let Some(oldest) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = state.entries.remove(&oldest) {
+                state.bytes -= entry.bytes;
+            }
+        }
+
+        state.bytes += bytes;
+        state.entries.insert(
+            key,
+            CacheEntry {
+                value,
+                expires_at: now + ttl,
+                last_used: now,
+                bytes,
+            },
+        );
+    }
+
+    fn state(&self) -> MutexGuard<'_, CacheState> {
+        self.store.state.lock().expect("cache state poisoned")
+    }
+}
+
+/// Rough heap + inline size of an entry, used for the memory budget
+fn estimate_size(key: &CacheKey, value: &CachedValue) -> usize {
+    let key_bytes = match key {
+        CacheKey::AquariumIds => 0,
+        CacheKey::SeriesMetadata { aquarium_id, .. } => aquarium_id.len(),
+        CacheKey::SingleValue(query) => query.len(),
+        CacheKey::TimeSeries { query, .. } => query.len(),
+        CacheKey::TaggedSeries { query, .. } => query.len(),
+    };
+    let value_bytes = match value {
+        CachedValue::AquariumIds(ids) => ids.iter().map(|id| id.len() + size_of::<String>()).sum(),
+        CachedValue::SeriesMetadata(series) => series
+            .iter()
+            .map(|s| {
+                s.measurement.len()
+                    + s.tags.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>()
+                    + size_of::<SeriesMetadata>()
+            })
+            .sum(),
+        CachedValue::SingleValue(_) => 0,
+        CachedValue::TimeSeries(points) => points.len() * size_of::<TimeSeriesPoint>(),
+        CachedValue::TaggedSeries(series) => series
+            .iter()
+            .map(|s| {
+                s.tags.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>()
+                    + s.points.len() * size_of::<TimeSeriesPoint>()
+                    + size_of::<TaggedSeries>()
+            })
+            .sum(),
+    };
+    key_bytes + value_bytes + size_of::<CacheKey>() + size_of::<CacheEntry>()
+}
+
+#[async_trait]
+impl TelemetryRepository for CachingRepository {
+    async fn list_aquarium_ids(&self) -> anyhow::Result<Vec<String>> {
+        let key = CacheKey::AquariumIds;
+        if let Some(CachedValue::AquariumIds(ids)) = self.get(&key) {
+            return Ok(ids);
+        }
+
+        let ids = self.inner.list_aquarium_ids().await?;
+        self.put(key, CachedValue::AquariumIds(ids.clone()), self.store.ttls.aquarium_ids);
+        Ok(ids)
+    }
+
+    async fn get_series_metadata(&self, aquarium_id: &str, range: &TimeRange) -> anyhow::Result<Vec<SeriesMetadata>> {
+        let key = CacheKey::SeriesMetadata {
+            aquarium_id: aquarium_id.to_string(),
+            range: *range,
+        };
+        if let Some(CachedValue::SeriesMetadata(series)) = self.get(&key) {
+            return Ok(series);
+        }
+
+        let series = self.inner.get_series_metadata(aquarium_id, range).await?;
+        self.put(key, CachedValue::SeriesMetadata(series.clone()), self.store.ttls.series_metadata);
+        Ok(series)
+    }
+
+    async fn query_single_value(&self, query: &str) -> anyhow::Result<Option<f64>> {
+        let key = CacheKey::SingleValue(query.to_string());
+        if let Some(CachedValue::SingleValue(value)) = self.get(&key) {
+            return Ok(value);
+        }
+
+        let value = self.inner.query_single_value(query).await?;
+        self.put(key, CachedValue::SingleValue(value), self.store.ttls.single_value);
+        Ok(value)
+    }
+
+    async fn query_single_values(
+        &self,
+        queries: &[String],
+    ) -> anyhow::Result<Vec<anyhow::Result<Option<f64>>>> {
+        // Serve what we can from the cache and batch only the misses
+        let mut results: Vec<Option<anyhow::Result<Option<f64>>>> = Vec::with_capacity(queries.len());
+        let mut missing = Vec::new();
+        for query in queries {
+            match self.get(&CacheKey::SingleValue(query.clone())) {
+                Some(CachedValue::SingleValue(value)) => results.push(Some(Ok(value))),
+                _ => {
+                    missing.push(query.clone());
+                    results.push(None);
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            let mut fetched = self.inner.query_single_values(&missing).await?.into_iter();
+            for (slot, query) in results
+                .iter_mut()
+                .filter(|slot| slot.is_none())
+                .zip(missing)
+            {
+                let value = fetched
+                    .next()
+                    .unwrap_or_else(|| Err(anyhow::anyhow!("No result for query in batch")));
+                if let Ok(v) = &value {
+                    self.put(CacheKey::SingleValue(query), CachedValue::SingleValue(*v), self.store.ttls.single_value);
+                }
+                *slot = Some(value);
+            }
+        }
+
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    async fn query_time_series_downsampled(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> anyhow::Result<Vec<TimeSeriesPoint>> {
+        let key = CacheKey::TimeSeries {
+            query: query.to_string(),
+            max_points,
+            downsampling,
+        };
+        if let Some(CachedValue::TimeSeries(points)) = self.get(&key) {
+            return Ok(points);
+        }
+
+        let points = self.inner.query_time_series_downsampled(query, max_points, downsampling).await?;
+        self.put(key, CachedValue::TimeSeries(points.clone()), self.store.ttls.time_series);
+        Ok(points)
+    }
+
+    async fn query_tagged_series(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> anyhow::Result<Vec<TaggedSeries>> {
+        let key = CacheKey::TaggedSeries {
+            query: query.to_string(),
+            max_points,
+            downsampling,
+        };
+        if let Some(CachedValue::TaggedSeries(series)) = self.get(&key) {
+            return Ok(series);
+        }
+
+        let series = self.inner.query_tagged_series(query, max_points, downsampling).await?;
+        self.put(key, CachedValue::TaggedSeries(series.clone()), self.store.ttls.time_series);
+        Ok(series)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Counts backend calls; every series has three points
+    #[derive(Default)]
+    struct CountingRepository {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TelemetryRepository for CountingRepository {
+        async fn list_aquarium_ids(&self) -> anyhow::Result<Vec<String>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(vec!["reef".to_string()])
+        }
+
+        async fn get_series_metadata(&self, _aquarium_id: &str, _range: &TimeRange) -> anyhow::Result<Vec<SeriesMetadata>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Vec::new())
+        }
+
+        async fn query_single_value(&self, _query: &str) -> anyhow::Result<Option<f64>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(1.0))
+        }
+
+        async fn query_time_series_downsampled(
+            &self,
+            _query: &str,
+            _max_points: usize,
+            _downsampling: Downsampling,
+        ) -> anyhow::Result<Vec<TimeSeriesPoint>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok((0..3).map(|i| TimeSeriesPoint::new(i, 1.0)).collect())
+        }
+    }
+
+    const TTLS: CacheTtls = CacheTtls {
+        single_value: Duration::from_secs(10),
+        time_series: Duration::from_secs(60),
+        series_metadata: Duration::from_secs(60),
+        aquarium_ids: Duration::from_secs(60),
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn test_hits_until_ttl_expires() {
+        let inner = Arc::new(CountingRepository::default());
+        let cache = CachingRepository::new(inner.clone(), TTLS, 1 << 20);
+
+        cache.query_single_value("SELECT 1").await.unwrap();
+        cache.query_single_value("SELECT 1").await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::advance(Duration::from_secs(11)).await;
+        cache.query_single_value("SELECT 1").await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_views_share_entries() {
+        let inner = Arc::new(CountingRepository::default());
+        let cache = CachingRepository::new(inner.clone(), TTLS, 1 << 20);
+        cache.query_single_value("SELECT 1").await.unwrap();
+
+        // A stream's view answers hits itself and sends only misses to its own inner
+        let stream_inner = Arc::new(CountingRepository::default());
+        let view = cache.with_inner(stream_inner.clone());
+        view.query_single_value("SELECT 1").await.unwrap();
+        view.query_single_value("SELECT 2").await.unwrap();
+
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(stream_inner.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_batch_only_fetches_misses() {
+        let inner = Arc::new(CountingRepository::default());
+        let cache = CachingRepository::new(inner.clone(), TTLS, 1 << 20);
+
+        cache.query_single_value("SELECT a").await.unwrap();
+        let values = cache
+            .query_single_values(&["SELECT a".to_string(), "SELECT b".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(values.len(), 2);
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_memory_budget_evicts_least_recently_used() {
+        let inner = Arc::new(CountingRepository::default());
+        let entry_size = estimate_size(
+            &CacheKey::TimeSeries {
+                query: "SELECT a".to_string(),
+                max_points: 150,
+                downsampling: Downsampling::Average,
+            },
+            &CachedValue::TimeSeries((0..3).map(|i| TimeSeriesPoint::new(i, 1.0)).collect()),
+        );
+        let cache = CachingRepository::new(inner.clone(), TTLS, entry_size * 2);
+
+        cache.query_time_series_downsampled("SELECT a", 150, Downsampling::Average).await.unwrap();
+        tokio::time::advance(Duration::from_secs(1)).await;
+        cache.query_time_series_downsampled("SELECT b", 150, Downsampling::Average).await.unwrap();
+        tokio::time::advance(Duration::from_secs(1)).await;
+        cache.query_time_series_downsampled("SELECT c", 150, Downsampling::Average).await.unwrap();
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert!(stats.bytes <= entry_size * 2);
+
+        // "SELECT a" was evicted, "SELECT c" is still cached
+        cache.query_time_series_downsampled("SELECT c", 150, Downsampling::Average).await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+        cache.query_time_series_downsampled("SELECT a", 150, Downsampling::Average).await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 4);
+    }
+}
@@ -2,8 +2,10 @@
 use crate::application::telemetry_repository::TelemetryRepository;
 use crate::domain::aquarium::Aquarium;
 use crate::domain::dashboard::Dashboard;
+use crate::domain::downsampling::DEFAULT_POINTS_PER_SERIES;
 use crate::domain::telemetry::{ChartData, ChartKind, SeriesData, TileData};
-use crate::infrastructure::config::{prepare_query, WidgetsConfig};
+use crate::domain::time_range::TimeRange;
+use crate::infrastructure::config::{prepare_query, Backend, WidgetsConfig};
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -21,14 +23,24 @@ impl DashboardService {
         }
     }
 
-    pub async fn get_dashboard(&self, aquarium_id: &str, hours: i32) -> anyhow::Result<Dashboard> {
+    pub async fn get_dashboard(&self, aquarium_id: &str, range: &TimeRange) -> anyhow::Result<Dashboard> {
         let aquarium = Aquarium::new(aquarium_id.to_string());
-        let title = format!("{} Telemetry (last {}h)", aquarium.name, hours);
+        let period = match *range {
+            TimeRange::Last { hours } => format!("last {}h", hours),
+            TimeRange::Between { start_ms, end_ms } => {
+                let time = |ms| {
+                    chrono::DateTime::from_timestamp_millis(ms)
+                        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_else(|| ms.to_string())
+                };
+                format!("{} to {} UTC", time(start_ms), time(end_ms))
+            }
+        };
+        let title = format!("{} Telemetry ({})", aquarium.name, period);
 
         // Prepare query variables
-        let mut vars = HashMap::new();
-        vars.insert("source".to_string(), aquarium_id.to_string());
-        vars.insert("hours".to_string(), hours.to_string());
+        let mut vars = Backend::InfluxQL.range_vars(range, DEFAULT_POINTS_PER_SERIES);
+        vars.insert("source".to_string(), Backend::InfluxQL.escape_string(aquarium_id));
 
         // Fetch tiles
         let tiles = self.fetch_tiles(&vars).await;
@@ -74,7 +86,7 @@ impl DashboardService {
 
             for series_config in &chart_config.series {
                 let query = prepare_query(&series_config.query, vars);
-                match self.repository.query_time_series_downsampled(&query, 150).await {
+                match self.repository.query_time_series_downsampled(&query, DEFAULT_POINTS_PER_SERIES, chart_config.downsampling).await {
                     Ok(points) => {
                         if !points.is_empty() {
                             series_list.push(SeriesData::new(
@@ -116,3 +128,52 @@ impl DashboardService {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::telemetry_repository::SeriesMetadata;
+    use crate::domain::downsampling::Downsampling;
+    use crate::domain::telemetry::TimeSeriesPoint;
+    use async_trait::async_trait;
+
+    struct EmptyRepository;
+
+    #[async_trait]
+    impl TelemetryRepository for EmptyRepository {
+        async fn list_aquarium_ids(&self) -> anyhow::Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        async fn get_series_metadata(&self, _aquarium_id: &str, _range: &TimeRange) -> anyhow::Result<Vec<SeriesMetadata>> {
+            Ok(Vec::new())
+        }
+
+        async fn query_single_value(&self, _query: &str) -> anyhow::Result<Option<f64>> {
+            Ok(None)
+        }
+
+        async fn query_time_series_downsampled(
+            &self,
+            _query: &str,
+            _max_points: usize,
+            _downsampling: Downsampling,
+        ) -> anyhow::Result<Vec<TimeSeriesPoint>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_title_describes_the_range() {
+        let service = DashboardService::new(Arc::new(EmptyRepository), WidgetsConfig::default());
+
+        let relative = service.get_dashboard("Planet_72", &TimeRange::Last { hours: 24 }).await.unwrap();
+        assert_eq!(relative.title, "Planet 72 Telemetry (last 24h)");
+
+        let absolute = TimeRange::Between {
+            start_ms: 1_735_688_400_000,
+            end_ms: 1_735_689_600_000,
+        };
+        let absolute = service.get_dashboard("Planet_72", &absolute).await.unwrap();
+        assert_eq!(absolute.title, "Planet 72 Telemetry (2024-12-31 23:40 to 2025-01-01 00:00 UTC)");
+    }
+}
@@ -1,6 +1,9 @@
 // Application layer - Use cases and application services
 pub mod aquarium_service;
+pub mod caching_repository;
 pub mod dashboard_service;
+pub mod query_scheduler;
+pub mod single_flight;
 pub mod streaming_service;
 pub mod telemetry_repository;
 
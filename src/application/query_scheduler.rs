@@ -0,0 +1,305 @@
+// Query scheduler - Bounds concurrent repository queries across dashboard streams
+use crate::application::telemetry_repository::{SeriesMetadata, TelemetryRepository};
+use crate::domain::downsampling::Downsampling;
+use crate::domain::telemetry::{TaggedSeries, TimeSeriesPoint};
+use crate::domain::time_range::TimeRange;
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::oneshot;
+
+/// Queue priority within a stream; tiles go ahead of chart series so the first screen fills quickly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryPriority {
+    Tile,
+    Series,
+}
+
+/// Hands out query permits with a global in-flight limit and a per-stream limit.
+/// Streams with queued queries are served round-robin so one large dashboard
+/// cannot starve the others.
+pub struct QueryScheduler {
+    max_in_flight: usize,
+    max_per_stream: usize,
+    state: Mutex<SchedulerState>,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    next_stream_id: u64,
+    in_flight: usize,
+    /// Streams with queued queries, in round-robin order
+    ready: VecDeque<u64>,
+    streams: HashMap<u64, StreamQueue>,
+}
+
+#[derive(Default)]
+struct StreamQueue {
+    in_flight: usize,
+    tiles: VecDeque<oneshot::Sender<QueryPermit>>,
+    series: VecDeque<oneshot::Sender<QueryPermit>>,
+}
+
+impl StreamQueue {
+    /// Pop the next waiter that is still listening, tiles first
+    fn next_waiter(&mut self) -> Option<oneshot::Sender<QueryPermit>> {
+        for queue in [&mut self.tiles, &mut self.series] {
+            while let Some(waiter) = queue.pop_front() {
+                if !waiter.is_closed() {
+                    return Some(waiter);
+                }
+            }
+        }
+        None
+    }
+
+    fn is_idle(&self) -> bool {
+        self.in_flight == 0 && self.tiles.is_empty() && self.series.is_empty()
+    }
+}
+
+/// A granted query slot; the slot is released when the permit is dropped
+pub struct QueryPermit {
+    scheduler: Arc<QueryScheduler>,
+    stream_id: u64,
+}
+
+impl Drop for QueryPermit {
+    fn drop(&mut self) {
+        self.scheduler.release(self.stream_id);
+    }
+}
+
+impl QueryScheduler {
+    pub fn new(max_in_flight: usize, max_per_stream: usize) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            max_per_stream: max_per_stream.max(1),
+            state: Mutex::new(SchedulerState::default()),
+        }
+    }
+
+    /// Create a repository view for one dashboard stream; all of its queries share the stream's limit
+    pub fn stream(self: &Arc<Self>, inner: Arc<dyn TelemetryRepository>) -> ScheduledRepository {
+        let stream_id = {
+            let mut state = self.state();
+            state.next_stream_id += 1;
+            state.next_stream_id
+        };
+
+        ScheduledRepository {
+            scheduler: self.clone(),
+            stream_id,
+            inner,
+        }
+    }
+
+    /// Wait for a query slot for the given stream
+    pub async fn acquire(
+        self: &Arc<Self>,
+        stream_id: u64,
+        priority: QueryPriority,
+    ) -> anyhow::Result<QueryPermit> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut state = self.state();
+            let queue = state.streams.entry(stream_id).or_default();
+            match priority {
+                QueryPriority::Tile => queue.tiles.push_back(tx),
+                QueryPriority::Series => queue.series.push_back(tx),
+            }
+            if !state.ready.contains(&stream_id) {
+                state.ready.push_back(stream_id);
+            }
+        }
+
+        self.dispatch();
+        rx.await
+            .map_err(|_| anyhow::anyhow!("Query scheduler dropped the request"))
+    }
+
+    /// Grant permits while there is global capacity, visiting ready streams round-robin
+    fn dispatch(self: &Arc<Self>) {
+        // Permits whose waiter went away are dropped after the lock is released,
+        // since dropping a permit re-enters the scheduler
+        let mut rejected = Vec::new();
+        {
+            let mut guard = self.state();
+            let state = &mut *guard;
+            let mut skipped = 0;
+
+            while state.in_flight < self.max_in_flight && skipped < state.ready.len() {
+                let Some(stream_id) = state.ready.pop_front() else {
+                    break;
+                };
+                let queue = state.streams.entry(stream_id).or_default();
+
+                if queue.in_flight >= self.max_per_stream {
+                    state.ready.push_back(stream_id);
+                    skipped += 1;
+                    continue;
+                }
+
+                let Some(waiter) = queue.next_waiter() else {
+                    if queue.is_idle() {
+                        state.streams.remove(&stream_id);
+                    }
+                    continue;
+                };
+
+                queue.in_flight += 1;
+                state.in_flight += 1;
+                if !queue.tiles.is_empty() || !queue.series.is_empty() {
+                    state.ready.push_back(stream_id);
+                }
+                skipped = 0;
+
+                let permit = QueryPermit {
+                    scheduler: self.clone(),
+                    stream_id,
+                };
+                if let Err(permit) = waiter.send(permit) {
+                    rejected.push(permit);
+                }
+            }
+        }
+        drop(rejected);
+    }
+
+    fn release(self: &Arc<Self>, stream_id: u64) {
+        {
+            let mut state = self.state();
+            state.in_flight = state.in_flight.saturating_sub(1);
+            if let Some(queue) = state.streams.get_mut(&stream_id) {
+                queue.in_flight = queue.in_flight.saturating_sub(1);
+                if queue.is_idle() {
+                    state.streams.remove(&stream_id);
+                }
+            }
+        }
+        self.dispatch();
+    }
+
+    fn state(&self) -> MutexGuard<'_, SchedulerState> {
+        self.state.lock().expect("query scheduler state poisoned")
+    }
+}
+
+/// `TelemetryRepository` for a single dashboard stream; every query waits for a scheduler permit.
+/// Single-value queries run at tile priority, time series at series priority.
+pub struct ScheduledRepository {
+    scheduler: Arc<QueryScheduler>,
+    stream_id: u64,
+    inner: Arc<dyn TelemetryRepository>,
+}
+
+impl ScheduledRepository {
+    async fn permit(&self, priority: QueryPriority) -> anyhow::Result<QueryPermit> {
+        self.scheduler.acquire(self.stream_id, priority).await
+    }
+}
+
+#[async_trait]
+impl TelemetryRepository for ScheduledRepository {
+    async fn list_aquarium_ids(&self) -> anyhow::Result<Vec<String>> {
+        let _permit = self.permit(QueryPriority::Tile).await?;
+        self.inner.list_aquarium_ids().await
+    }
+
+    async fn get_series_metadata(&self, aquarium_id: &str, range: &TimeRange) -> anyhow::Result<Vec<SeriesMetadata>> {
+        let _permit = self.permit(QueryPriority::Tile).await?;
+        self.inner.get_series_metadata(aquarium_id, range).await
+    }
+
+    async fn query_single_value(&self, query: &str) -> anyhow::Result<Option<f64>> {
+        let _permit = self.permit(QueryPriority::Tile).await?;
+        self.inner.query_single_value(query).await
+    }
+
+    async fn query_single_values(
+        &self,
+        queries: &[String],
+    ) -> anyhow::Result<Vec<anyhow::Result<Option<f64>>>> {
+        let _permit = self.permit(QueryPriority::Tile).await?;
+        self.inner.query_single_values(queries).await
+    }
+
+    async fn query_time_series_downsampled(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> anyhow::Result<Vec<TimeSeriesPoint>> {
+        let _permit = self.permit(QueryPriority::Series).await?;
+        self.inner.query_time_series_downsampled(query, max_points, downsampling).await
+    }
+
+    async fn query_tagged_series(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> anyhow::Result<Vec<TaggedSeries>> {
+        let _permit = self.permit(QueryPriority::Series).await?;
+        self.inner.query_tagged_series(query, max_points, downsampling).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[tokio::test]
+    async fn test_global_and_per_stream_limits() {
+        let scheduler = Arc::new(QueryScheduler::new(2, 1));
+
+        let first = scheduler.acquire(1, QueryPriority::Tile).now_or_never();
+        assert!(first.is_some());
+
+        // Stream 1 is at its own limit, stream 2 still gets the last global slot
+        assert!(scheduler.acquire(1, QueryPriority::Tile).now_or_never().is_none());
+        let second = scheduler.acquire(2, QueryPriority::Tile).now_or_never();
+        assert!(second.is_some());
+
+        // Global limit reached
+        assert!(scheduler.acquire(3, QueryPriority::Tile).now_or_never().is_none());
+
+        drop(first);
+        assert!(scheduler.acquire(3, QueryPriority::Tile).now_or_never().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_round_robin_with_tiles_first() {
+        let scheduler = Arc::new(QueryScheduler::new(1, 4));
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let blocker = scheduler.acquire(1, QueryPriority::Tile).await.unwrap();
+
+        let mut handles = Vec::new();
+        for (stream_id, priority, label) in [
+            (1, QueryPriority::Series, "a-series-1"),
+            (1, QueryPriority::Series, "a-series-2"),
+            (2, QueryPriority::Series, "b-series"),
+            (1, QueryPriority::Tile, "a-tile"),
+        ] {
+            let scheduler = scheduler.clone();
+            let order = order.clone();
+            handles.push(tokio::spawn(async move {
+                let _permit = scheduler.acquire(stream_id, priority).await.unwrap();
+                order.lock().unwrap().push(label);
+            }));
+        }
+
+        // Let every task enqueue before the slot frees up
+        tokio::task::yield_now().await;
+        drop(blocker);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["a-tile", "b-series", "a-series-1", "a-series-2"]
+        );
+    }
+}
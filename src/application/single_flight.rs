@@ -0,0 +1,283 @@
+// Single-flight repository - Coalesces identical concurrent queries onto one backend call
+use crate::application::telemetry_repository::{SeriesMetadata, TelemetryRepository};
+use crate::domain::downsampling::Downsampling;
+use crate::domain::telemetry::{TaggedSeries, TimeSeriesPoint};
+use crate::domain::time_range::TimeRange;
+use async_trait::async_trait;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+type SharedResult<T> = Result<T, Arc<anyhow::Error>>;
+type SharedQuery<T> = Shared<BoxFuture<'static, SharedResult<T>>>;
+
+/// In-flight calls for one repository method, keyed on the prepared query
+struct Flights<K, T: Clone> {
+    in_flight: Mutex<HashMap<K, Flight<T>>>,
+}
+
+/// A running call and the number of callers awaiting it
+struct Flight<T: Clone> {
+    query: SharedQuery<T>,
+    waiters: usize,
+}
+
+impl<K, T> Flights<K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone + Send + Sync + 'static,
+{
+    fn new() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Join the call already running for `key`, or start one with `start`
+    async fn run<F>(&self, key: K, start: F) -> anyhow::Result<T>
+    where
+        F: FnOnce() -> BoxFuture<'static, anyhow::Result<T>>,
+    {
+        let query = {
+            let mut in_flight = self.in_flight.lock().expect("single-flight state poisoned");
+            let flight = in_flight.entry(key.clone()).or_insert_with(|| Flight {
+                query: start().map(|r| r.map_err(Arc::new)).boxed().shared(),
+                waiters: 0,
+            });
+            flight.waiters += 1;
+            flight.query.clone()
+        };
+
+        let mut waiter = Waiter {
+            flights: self,
+            key,
+            query: query.clone(),
+            finished: false,
+        };
+        let result = query.await;
+        waiter.finished = true;
+        drop(waiter);
+
+        result.map_err(|e| anyhow::anyhow!("{:#}", e))
+    }
+}
+
+/// Leaves a flight when its caller finishes or is cancelled. Whoever finishes first
+/// clears the entry, as does the last caller to give up, so a call nobody awaits
+/// any more is dropped instead of staying in the map.
+struct Waiter<'a, K: Hash + Eq, T: Clone> {
+    flights: &'a Flights<K, T>,
+    key: K,
+    query: SharedQuery<T>,
+    finished: bool,
+}
+
+impl<K: Hash + Eq, T: Clone> Drop for Waiter<'_, K, T> {
+    fn drop(&mut self) {
+        let mut in_flight = self.flights.in_flight.lock().expect("single-flight state poisoned");
+        // A newer call may already have replaced the entry
+        let Some(flight) = in_flight.get_mut(&self.key).filter(|f| f.query.ptr_eq(&self.query)) else {
+            return;
+        };
+        flight.waiters -= 1;
+        if self.finished || flight.waiters == 0 {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+/// Wraps any `TelemetryRepository` so concurrent callers of the same prepared
+/// query share one backend call and its result, errors included.
+pub struct SingleFlightRepository {
+    inner: Arc<dyn TelemetryRepository>,
+    aquarium_ids: Flights<(), Vec<String>>,
+    series_metadata: Flights<(String, TimeRange), Vec<SeriesMetadata>>,
+    single_values: Flights<String, Option<f64>>,
+    single_value_batches: Flights<Vec<String>, Vec<SharedResult<Option<f64>>>>,
+    time_series: Flights<(String, usize, Downsampling), Vec<TimeSeriesPoint>>,
+    tagged_series: Flights<(String, usize, Downsampling), Vec<TaggedSeries>>,
+}
+
+impl SingleFlightRepository {
+    pub fn new(inner: Arc<dyn TelemetryRepository>) -> Self {
+        Self {
+            inner,
+            aquarium_ids: Flights::new(),
+            series_metadata: Flights::new(),
+            single_values: Flights::new(),
+            single_value_batches: Flights::new(),
+            time_series: Flights::new(),
+            tagged_series: Flights::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl TelemetryRepository for SingleFlightRepository {
+    async fn list_aquarium_ids(&self) -> anyhow::Result<Vec<String>> {
+        let inner = self.inner.clone();
+        self.aquarium_ids
+            .run((), || async move { inner.list_aquarium_ids().await }.boxed())
+            .await
+    }
+
+    async fn get_series_metadata(&self, aquarium_id: &str, range: &TimeRange) -> anyhow::Result<Vec<SeriesMetadata>> {
+        let inner = self.inner.clone();
+        let id = aquarium_id.to_string();
+        let range = *range;
+        self.series_metadata
+            .run((id.clone(), range), || {
+                async move { inner.get_series_metadata(&id, &range).await }.boxed()
+            })
+            .await
+    }
+
+    async fn query_single_value(&self, query: &str) -> anyhow::Result<Option<f64>> {
+        let inner = self.inner.clone();
+        let query = query.to_string();
+        self.single_values
+            .run(query.clone(), || {
+                async move { inner.query_single_value(&query).await }.boxed()
+            })
+            .await
+    }
+
+    async fn query_single_values(
+        &self,
+        queries: &[String],
+    ) -> anyhow::Result<Vec<anyhow::Result<Option<f64>>>> {
+        let inner = self.inner.clone();
+        let batch = queries.to_vec();
+        let results = self
+            .single_value_batches
+            .run(batch.clone(), || {
+                async move {
+                    let results = inner.query_single_values(&batch).await?;
+                    Ok(results.into_iter().map(|r| r.map_err(Arc::new)).collect())
+                }
+                .boxed()
+            })
+            .await?;
+
+        Ok(results
+            .into_iter()
+            .map(|r| r.map_err(|e| anyhow::anyhow!("{:#}", e)))
+            .collect())
+    }
+
+    async fn query_time_series_downsampled(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> anyhow::Result<Vec<TimeSeriesPoint>> {
+        let inner = self.inner.clone();
+        let query = query.to_string();
+        self.time_series
+            .run((query.clone(), max_points, downsampling), || {
+                async move { inner.query_time_series_downsampled(&query, max_points, downsampling).await }.boxed()
+            })
+            .await
+    }
+
+    async fn query_tagged_series(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> anyhow::Result<Vec<TaggedSeries>> {
+        let inner = self.inner.clone();
+        let query = query.to_string();
+        self.tagged_series
+            .run((query.clone(), max_points, downsampling), || {
+                async move { inner.query_tagged_series(&query, max_points, downsampling).await }.boxed()
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::join_all;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Slow backend that counts calls; queries mentioning "bad" fail
+    #[derive(Default)]
+    struct SlowRepository {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TelemetryRepository for SlowRepository {
+        async fn list_aquarium_ids(&self) -> anyhow::Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        async fn get_series_metadata(&self, _aquarium_id: &str, _range: &TimeRange) -> anyhow::Result<Vec<SeriesMetadata>> {
+            Ok(Vec::new())
+        }
+
+        async fn query_single_value(&self, _query: &str) -> anyhow::Result<Option<f64>> {
+            Ok(None)
+        }
+
+        async fn query_time_series_downsampled(
+            &self,
+            query: &str,
+            _max_points: usize,
+            _downsampling: Downsampling,
+        ) -> anyhow::Result<Vec<TimeSeriesPoint>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if query.contains("bad") {
+                anyhow::bail!("InfluxDB query error: bad query");
+            }
+            Ok(vec![TimeSeriesPoint::new(0, 7.9)])
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_callers_share_one_call() {
+        let inner = Arc::new(SlowRepository::default());
+        let repo = SingleFlightRepository::new(inner.clone());
+
+        let results = join_all((0..8).map(|_| repo.query_time_series_downsampled("SELECT pH", 150, Downsampling::Lttb))).await;
+
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(|r| r.as_ref().unwrap().len() == 1));
+
+        // Once the call finished, the next caller starts a fresh one
+        repo.query_time_series_downsampled("SELECT pH", 150, Downsampling::Lttb).await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_errors_are_shared() {
+        let inner = Arc::new(SlowRepository::default());
+        let repo = SingleFlightRepository::new(inner.clone());
+
+        let results = join_all((0..4).map(|_| repo.query_time_series_downsampled("SELECT bad", 150, Downsampling::Lttb))).await;
+
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+        for result in results {
+            assert!(result.unwrap_err().to_string().contains("bad query"));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_callers_leave_no_flight() {
+        let inner = Arc::new(SlowRepository::default());
+        let repo = SingleFlightRepository::new(inner.clone());
+
+        let callers = join_all((0..3).map(|_| repo.query_time_series_downsampled("SELECT pH", 150, Downsampling::Lttb)));
+        assert!(tokio::time::timeout(Duration::from_millis(10), callers).await.is_err());
+        assert!(repo.time_series.in_flight.lock().unwrap().is_empty());
+
+        // The abandoned call is not joined; the next caller starts a fresh one
+        repo.query_time_series_downsampled("SELECT pH", 150, Downsampling::Lttb).await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,9 +1,21 @@
 // Streaming dashboard service - Progressive loading with chunked Thrift
-use crate::application::telemetry_repository::{ProbeMetadata, TelemetryRepository};
-use crate::infrastructure::config::{prepare_query, WidgetsConfig};
+use crate::application::caching_repository::CachingRepository;
+use crate::application::query_scheduler::QueryScheduler;
+use crate::application::telemetry_repository::{is_discovered, SeriesMetadata, TelemetryRepository};
+use crate::domain::downsampling::Downsampling;
+use crate::domain::telemetry::TimeSeriesPoint;
+use crate::domain::time_range::TimeRange;
+use crate::infrastructure::config::{
+    prepare_query, Backend, GroupedSeriesConfig, PageConfig, SeriesConfig, SeriesGroupConfig,
+    WidgetsConfig,
+};
+use crate::infrastructure::config_reload::ConfigHandle;
+use crate::infrastructure::influxql_filter;
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use std::time::Instant;
+use std::future::Future;
+use std::time::{Duration, Instant};
 use telemetry_thrift::{
     ChartSkeleton, ChartUpdate, CompletionEvent, DashboardSkeleton, OverlaySkeleton,
     OverlayUpdate, SDPoint, SeriesSkeleton, SeriesUpdate, StreamMessage, StreamMessageType,
@@ -11,53 +23,154 @@ use telemetry_thrift::{
 };
 use thrift::OrderedFloat;
 use tokio::sync::mpsc;
+use tokio::task::{AbortHandle, JoinSet};
+
+/// Per-widget deadline; a query still running after this is counted as failed
+const WIDGET_DEADLINE: Duration = Duration::from_secs(20);
+
+/// Result of a single tile, series or overlay task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WidgetOutcome {
+    /// An update was sent to the client
+    Delivered,
+    /// The query succeeded but returned no data
+    Empty,
+    /// The query failed or hit the deadline
+    Failed,
+}
+
+/// Widget counts for a finished dashboard stream
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StreamSummary {
+    pub delivered: usize,
+    pub empty: usize,
+    pub failed: usize,
+}
+
+impl StreamSummary {
+    fn record(&mut self, outcome: WidgetOutcome) {
+        match outcome {
+            WidgetOutcome::Delivered => self.delivered += 1,
+            WidgetOutcome::Empty => self.empty += 1,
+            WidgetOutcome::Failed => self.failed += 1,
+        }
+    }
+
+    fn merge(&mut self, other: StreamSummary) {
+        self.delivered += other.delivered;
+        self.empty += other.empty;
+        self.failed += other.failed;
+    }
 
-const MAX_POINTS_PER_SERIES: usize = 150;
+    fn total(&self) -> usize {
+        self.delivered + self.empty + self.failed
+    }
+
+    /// The COMPLETE event for this stream. `CompletionEvent` in telemetry-thrift v0.1.0 only
+    /// has `total_widgets` and `duration_ms`; the delivered, empty and failed counts are set
+    /// here once it gains `delivered_widgets`, `empty_widgets` and `failed_widgets` fields.
+    fn completion_event(&self, duration_ms: i64) -> CompletionEvent {
+        CompletionEvent::new(Some(self.total() as i32), Some(duration_ms))
+    }
+}
+
+impl From<WidgetOutcome> for StreamSummary {
+    fn from(outcome: WidgetOutcome) -> Self {
+        let mut summary = StreamSummary::default();
+        summary.record(outcome);
+        summary
+    }
+}
 
 #[derive(Clone)]
 pub struct StreamingDashboardService {
-    repository: Arc<dyn TelemetryRepository>,
-    widgets_config: WidgetsConfig,
+    repository: Arc<CachingRepository>,
+    scheduler: Arc<QueryScheduler>,
+    widgets: Arc<ConfigHandle<WidgetsConfig>>,
+    backend: Backend,
 }
 
 impl StreamingDashboardService {
-    pub fn new(repository: Arc<dyn TelemetryRepository>, widgets_config: WidgetsConfig) -> Self {
+    pub fn new(
+        repository: Arc<CachingRepository>,
+        scheduler: Arc<QueryScheduler>,
+        widgets: Arc<ConfigHandle<WidgetsConfig>>,
+        backend: Backend,
+    ) -> Self {
         Self {
             repository,
-            widgets_config,
+            scheduler,
+            widgets,
+            backend,
         }
     }
 
+    /// Pages of the aquarium's dashboard, after its overrides
+    pub fn pages(&self, aquarium_id: &str) -> Vec<PageConfig> {
+        self.widgets.load().for_aquarium(aquarium_id).pages().cloned().collect()
+    }
+
+    /// Stream the dashboard, or one page of it, downsampling every series and overlay
+    /// to `points` points. None if `page` isn't one of the aquarium's `pages`.
     pub async fn stream_dashboard(
         &self,
         aquarium_id: &str,
-        hours: i32,
-    ) -> mpsc::Receiver<StreamMessage> {
-        let (tx, rx) = mpsc::channel(100);
+        page: Option<&str>,
+        range: &TimeRange,
+        points: usize,
+    ) -> Option<(mpsc::Receiver<StreamMessage>, StreamGuard)> {
         let start_time = Instant::now();
 
-        // 0. Get all probe metadata in a single efficient query
-        // Filter based on the selected time range to match what the user is viewing
-        let probe_metadata = self
-            .repository
-            .get_probe_metadata(aquarium_id, hours)
-            .await
-            .unwrap_or_default();
+        // The whole stream uses one snapshot, even if the config is reloaded meanwhile,
+        // merged with the aquarium's overrides. The page is looked up in the same snapshot.
+        let snapshot = self.widgets.load();
+        let widgets = snapshot.for_aquarium(aquarium_id);
+        let widgets = match page {
+            Some(page) if widgets.pages().any(|p| p.id == page) => Cow::Owned(widgets.page(page)?),
+            Some(_) => return None,
+            None => widgets,
+        };
+        let (tx, rx) = mpsc::channel(100);
 
-        // Debug: Log available probes
-        tracing::debug!(
-            "Available probes for {}: {} probes",
-            aquarium_id,
-            probe_metadata.len()
-        );
+        // Cache misses for this stream go through the scheduler, sharing one per-stream limit;
+        // cache hits are answered without waiting for a permit
+        let scheduled = Arc::new(self.scheduler.stream(self.repository.inner()));
+        let repository: Arc<dyn TelemetryRepository> = Arc::new(self.repository.with_inner(scheduled));
+
+        // 0. Discover the probes, pumps and outputs that have data for this aquarium
+        // Filter based on the selected time range to match what the user is viewing.
+        // If discovery fails, nothing is known to be missing, so no widget is filtered out.
+        let available_series: Option<HashSet<SeriesMetadata>> =
+            match repository.get_series_metadata(aquarium_id, range).await {
+                Ok(series_metadata) => {
+                    tracing::debug!(
+                        "Available series for {}: {} series",
+                        aquarium_id,
+                        series_metadata.len()
+                    );
+                    Some(series_metadata.into_iter().collect())
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Series discovery for {} failed, showing every widget: {:#}",
+                        aquarium_id,
+                        e
+                    );
+                    None
+                }
+            };
+        let available_series = available_series.as_ref();
 
-        // Build a set for fast lookup
-        let available_probes: HashSet<ProbeMetadata> = probe_metadata.into_iter().collect();
+        // Each chart's series, with its series template expanded over the discovered series
+        let no_series = HashSet::new();
+        let chart_series: Vec<Vec<SeriesConfig>> = widgets
+            .charts
+            .iter()
+            .map(|c| c.series_for(available_series.unwrap_or(&no_series)))
+            .collect();
 
-        // 1. Build and send skeleton immediately (filtered by available probes)
-        let skeleton = self.build_skeleton(aquarium_id, &available_probes);
-        let total_widgets = skeleton.tiles.as_ref().map(|t| t.len()).unwrap_or(0)
-            + skeleton.charts.as_ref().map(|c| c.len()).unwrap_or(0);
+        // 1. Build and send skeleton immediately (filtered by available series)
+        let skeleton = self.build_skeleton(aquarium_id, &widgets, available_series, &chart_series);
 
         let skeleton_msg = StreamMessage::new(
             Some(StreamMessageType::SKELETON),
@@ -68,73 +181,103 @@ impl StreamingDashboardService {
         );
         let _ = tx.send(skeleton_msg).await;
 
-        // 2. Spawn tasks for tiles (filtered by available probes)
-        for tile_config in &self.widgets_config.tiles {
-            // Check if this tile's probe exists
-            if !self.is_probe_available(&tile_config.query, &available_probes) {
-                continue;
-            }
+        // 2. Spawn one task for all tiles (filtered by available series), batched into one round trip
+        // Every widget task is tracked so COMPLETE can be sent once all of them are done
+        let mut tasks: JoinSet<StreamSummary> = JoinSet::new();
+        let mut widget_count = 0;
+        let vars = self.query_vars(aquarium_id, range, points);
+
+        let (tile_ids, tile_queries): (Vec<String>, Vec<String>) = widgets
+            .tiles
+            .iter()
+            .filter_map(|t| {
+                let query = self.widget_query(t.query_for(self.backend), &t.query, available_series)?;
+                Some((t.id.clone(), prepare_query(query, &vars)))
+            })
+            .unzip();
 
+        if !tile_ids.is_empty() {
+            widget_count += tile_ids.len();
             let tx = tx.clone();
-            let repo = self.repository.clone();
-            let tile_id = tile_config.id.clone();
-            let query = self.prepare_tile_query(&tile_config.query, aquarium_id, hours);
-
-            tokio::spawn(async move {
-                if let Ok(Some(value)) = repo.query_single_value(&query).await {
-                    let update = TileUpdate::new(Some(tile_id), Some(OrderedFloat::from(value)));
-                    let msg = StreamMessage::new(
-                        Some(StreamMessageType::TILE_UPDATE),
-                        None,
-                        Some(update),
-                        None,
-                        None,
-                    );
-                    let _ = tx.send(msg).await;
+            let repo = repository.clone();
+
+            tasks.spawn(with_deadline("tiles".to_string(), tile_ids.len(), async move {
+                let mut summary = StreamSummary::default();
+                let values = match repo.query_single_values(&tile_queries).await {
+                    Ok(values) => values,
+                    Err(e) => {
+                        tracing::warn!("Error fetching tiles: {}", e);
+                        summary.failed = tile_ids.len();
+                        return summary;
+                    }
+                };
+
+                for (tile_id, value) in tile_ids.into_iter().zip(values) {
+                    let outcome = match value {
+                        Ok(Some(value)) => {
+                            let update = TileUpdate::new(Some(tile_id), Some(OrderedFloat::from(value)));
+                            let msg = StreamMessage::new(
+                                Some(StreamMessageType::TILE_UPDATE),
+                                None,
+                                Some(update),
+                                None,
+                                None,
+                            );
+                            let _ = tx.send(msg).await;
+                            WidgetOutcome::Delivered
+                        }
+                        Ok(None) => WidgetOutcome::Empty,
+                        Err(e) => {
+                            tracing::warn!("Error fetching tile {}: {}", tile_id, e);
+                            WidgetOutcome::Failed
+                        }
+                    };
+                    summary.record(outcome);
                 }
-            });
+                summary
+            }));
         }
 
-        // 3. Spawn tasks for chart series (filtered by available probes, with downsampling)
-        for chart_config in &self.widgets_config.charts {
-            for series_config in &chart_config.series {
-                // Check if this series' probe exists
-                if !self.is_probe_available(&series_config.query, &available_probes) {
+        // 3. Spawn tasks for chart series (filtered by available series, with downsampling)
+        for (chart_config, series) in widgets.charts.iter().zip(&chart_series) {
+            for series_config in series {
+                // Check if this series has a query for the backend and it has data
+                let Some(series_query) = self.widget_query(
+                    series_config.query_for(self.backend),
+                    &series_config.query,
+                    available_series,
+                ) else {
                     tracing::debug!(
-                        "Skipping series {} for chart {} - probe not available",
+                        "Skipping series {} for chart {} - series not available",
                         series_config.id, chart_config.id
                     );
                     continue;
-                }
+                };
 
                 tracing::debug!(
-                    "Including series {} for chart {} - probe is available",
+                    "Including series {} for chart {} - series is available",
                     series_config.id, chart_config.id
                 );
 
                 let tx = tx.clone();
-                let repo = self.repository.clone();
+                let repo = repository.clone();
                 let chart_id = chart_config.id.clone();
                 let series_id = series_config.id.clone();
-                let query = self.prepare_chart_query(&series_config.query, aquarium_id, hours);
+                let query = prepare_query(series_query, &vars);
+                let downsampling = chart_config.downsampling;
 
-                tokio::spawn(async move {
+                widget_count += 1;
+                tasks.spawn(with_deadline(series_id.clone(), 1, async move {
                     // Query with server-side downsampling
-                    if let Ok(points) = repo
-                        .query_time_series_downsampled(&query, MAX_POINTS_PER_SERIES)
+                    match repo
+                        .query_time_series_downsampled(&query, points, downsampling)
                         .await
                     {
                         // Only send if we have data
-                        if !points.is_empty() {
-                            let sd_points: Vec<SDPoint> = points
-                                .into_iter()
-                                .map(|p| {
-                                    SDPoint::new(Some(p.time_ms), Some(OrderedFloat::from(p.value)))
-                                })
-                                .collect();
-
+                        Ok(points) if points.is_empty() => WidgetOutcome::Empty,
+                        Ok(points) => {
                             let series_update =
-                                SeriesUpdate::new(Some(series_id), Some(sd_points));
+                                SeriesUpdate::new(Some(series_id), Some(to_sd_points(points)));
                             let chart_update =
                                 ChartUpdate::new(Some(chart_id), Some(vec![series_update]), None);
                             let msg = StreamMessage::new(
@@ -145,39 +288,109 @@ impl StreamingDashboardService {
                                 None,
                             );
                             let _ = tx.send(msg).await;
+                            WidgetOutcome::Delivered
+                        }
+                        Err(e) => {
+                            tracing::warn!("Error fetching series {}: {}", series_id, e);
+                            WidgetOutcome::Failed
+                        }
+                    }
+                }));
+            }
+
+            // 3b. Spawn one task per series group; its single query fills all of its series
+            for group_config in &chart_config.series_groups {
+                let members = self.group_members(group_config, available_series);
+                if members.is_empty() {
+                    continue;
+                }
+                let Some(group_query) = group_config.query_for(self.backend) else {
+                    continue;
+                };
+
+                let tx = tx.clone();
+                let repo = repository.clone();
+                let chart_id = chart_config.id.clone();
+                let group_id = group_config.id.clone();
+                let tag = group_config.tag.clone();
+                let members: Vec<(String, String)> = members
+                    .into_iter()
+                    .map(|m| (m.id.clone(), m.tag_value().to_string()))
+                    .collect();
+                let query = prepare_query(group_query, &vars);
+                let downsampling = chart_config.downsampling;
+
+                widget_count += members.len();
+                tasks.spawn(with_deadline(group_id.clone(), members.len(), async move {
+                    let mut summary = StreamSummary::default();
+                    let mut tagged = match repo.query_tagged_series(&query, points, downsampling).await {
+                        Ok(tagged) => tagged,
+                        Err(e) => {
+                            tracing::warn!("Error fetching series group {}: {}", group_id, e);
+                            summary.failed = members.len();
+                            return summary;
+                        }
+                    };
+
+                    let mut series_updates = Vec::new();
+                    for (series_id, tag_value) in members {
+                        let points = tagged
+                            .iter_mut()
+                            .find(|t| t.tag(&tag) == Some(tag_value.as_str()))
+                            .map(|t| std::mem::take(&mut t.points))
+                            .unwrap_or_default();
+                        if points.is_empty() {
+                            summary.record(WidgetOutcome::Empty);
+                            continue;
                         }
+                        series_updates.push(SeriesUpdate::new(Some(series_id), Some(to_sd_points(points))));
+                        summary.record(WidgetOutcome::Delivered);
+                    }
+
+                    // Only send if we have data
+                    if !series_updates.is_empty() {
+                        let chart_update = ChartUpdate::new(Some(chart_id), Some(series_updates), None);
+                        let msg = StreamMessage::new(
+                            Some(StreamMessageType::CHART_UPDATE),
+                            None,
+                            None,
+                            Some(chart_update),
+                            None,
+                        );
+                        let _ = tx.send(msg).await;
                     }
-                });
+                    summary
+                }));
             }
 
-            // 3b. Spawn tasks for chart overlays (similar to series but for boolean status indicators)
+            // 3c. Spawn tasks for chart overlays (similar to series but for boolean status indicators)
             for overlay_config in &chart_config.overlays {
-                // Overlays from apex_output don't use probe metadata, so we don't filter them
-                // They will simply return empty if the output doesn't exist for this aquarium
+                let Some(overlay_query) = self.widget_query(
+                    overlay_config.query_for(self.backend),
+                    &overlay_config.query,
+                    available_series,
+                ) else {
+                    continue;
+                };
 
                 let tx = tx.clone();
-                let repo = self.repository.clone();
+                let repo = repository.clone();
                 let chart_id = chart_config.id.clone();
                 let overlay_id = overlay_config.id.clone();
-                let query = self.prepare_chart_query(&overlay_config.query, aquarium_id, hours);
+                let query = prepare_query(overlay_query, &vars);
 
-                tokio::spawn(async move {
+                widget_count += 1;
+                tasks.spawn(with_deadline(overlay_id.clone(), 1, async move {
                     // Query with server-side downsampling
-                    if let Ok(points) = repo
-                        .query_time_series_downsampled(&query, MAX_POINTS_PER_SERIES)
+                    match repo
+                        .query_time_series_downsampled(&query, points, Downsampling::Average)
                         .await
                     {
                         // Only send if we have data
-                        if !points.is_empty() {
-                            let sd_points: Vec<SDPoint> = points
-                                .into_iter()
-                                .map(|p| {
-                                    SDPoint::new(Some(p.time_ms), Some(OrderedFloat::from(p.value)))
-                                })
-                                .collect();
-
+                        Ok(points) if points.is_empty() => WidgetOutcome::Empty,
+                        Ok(points) => {
                             let overlay_update =
-                                OverlayUpdate::new(Some(overlay_id), Some(sd_points));
+                                OverlayUpdate::new(Some(overlay_id), Some(to_sd_points(points)));
                             let chart_update = ChartUpdate::new(
                                 Some(chart_id),
                                 Some(vec![]),
@@ -191,20 +404,34 @@ impl StreamingDashboardService {
                                 None,
                             );
                             let _ = tx.send(msg).await;
+                            WidgetOutcome::Delivered
+                        }
+                        Err(e) => {
+                            tracing::warn!("Error fetching overlay {}: {}", overlay_id, e);
+                            WidgetOutcome::Failed
                         }
                     }
-                });
+                }));
             }
         }
 
-        // 4. Spawn completion task
-        let tx_complete = tx.clone();
-        tokio::spawn(async move {
-            // Give queries time to complete (simple approach - wait a bit)
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+        // 4. Spawn completion task - waits for every widget task, then sends COMPLETE
+        // The task owns the JoinSet, so aborting it through the guard also aborts every widget query
+        let aquarium_id = aquarium_id.to_string();
+        let completion = tokio::spawn(async move {
+            let summary = collect_outcomes(tasks, widget_count).await;
 
             let duration_ms = start_time.elapsed().as_millis() as i64;
-            let complete = CompletionEvent::new(Some(total_widgets as i32), Some(duration_ms));
+            tracing::info!(
+                "Dashboard stream for {} complete in {}ms: {} delivered, {} empty, {} failed",
+                aquarium_id,
+                duration_ms,
+                summary.delivered,
+                summary.empty,
+                summary.failed
+            );
+
+            let complete = summary.completion_event(duration_ms);
             let msg = StreamMessage::new(
                 Some(StreamMessageType::COMPLETE),
                 None,
@@ -212,23 +439,27 @@ impl StreamingDashboardService {
                 None,
                 Some(complete),
             );
-            let _ = tx_complete.send(msg).await;
+            let _ = tx.send(msg).await;
         });
 
-        rx
+        Some((rx, StreamGuard::new(completion.abort_handle())))
     }
 
     fn build_skeleton(
         &self,
         aquarium_id: &str,
-        available_probes: &HashSet<ProbeMetadata>,
+        widgets: &WidgetsConfig,
+        available_series: Option<&HashSet<SeriesMetadata>>,
+        chart_series: &[Vec<SeriesConfig>],
     ) -> DashboardSkeleton {
-        // Filter tiles by available probes
-        let tiles: Vec<TileSkeleton> = self
-            .widgets_config
+        // Filter tiles by available series
+        let tiles: Vec<TileSkeleton> = widgets
             .tiles
             .iter()
-            .filter(|t| self.is_probe_available(&t.query, available_probes))
+            .filter(|t| {
+                self.widget_query(t.query_for(self.backend), &t.query, available_series)
+                    .is_some()
+            })
             .map(|t| {
                 TileSkeleton::new(
                     Some(t.id.clone()),
@@ -239,17 +470,19 @@ impl StreamingDashboardService {
             })
             .collect();
 
-        // Filter charts and their series by available probes
-        let charts: Vec<ChartSkeleton> = self
-            .widgets_config
+        // Filter charts, their series and overlays by available series
+        let charts: Vec<ChartSkeleton> = widgets
             .charts
             .iter()
-            .filter_map(|c| {
+            .zip(chart_series)
+            .filter_map(|(c, series)| {
                 // Filter series for this chart
-                let series: Vec<SeriesSkeleton> = c
-                    .series
+                let mut series: Vec<SeriesSkeleton> = series
                     .iter()
-                    .filter(|s| self.is_probe_available(&s.query, available_probes))
+                    .filter(|s| {
+                        self.widget_query(s.query_for(self.backend), &s.query, available_series)
+                            .is_some()
+                    })
                     .map(|s| {
                         SeriesSkeleton::new(
                             Some(s.id.clone()),
@@ -258,11 +491,26 @@ impl StreamingDashboardService {
                         )
                     })
                     .collect();
+                series.extend(
+                    c.series_groups
+                        .iter()
+                        .flat_map(|g| self.group_members(g, available_series))
+                        .map(|m| {
+                            SeriesSkeleton::new(
+                                Some(m.id.clone()),
+                                Some(m.name.clone()),
+                                m.color.clone(),
+                            )
+                        }),
+                );
 
-                // Build overlay skeletons (overlays don't need probe filtering)
                 let overlays: Vec<OverlaySkeleton> = c
                     .overlays
                     .iter()
+                    .filter(|o| {
+                        self.widget_query(o.query_for(self.backend), &o.query, available_series)
+                            .is_some()
+                    })
                     .map(|o| {
                         OverlaySkeleton::new(
                             Some(o.id.clone()),
@@ -305,70 +553,278 @@ impl StreamingDashboardService {
         DashboardSkeleton::new(Some(aquarium_id.to_string()), Some(tiles), Some(charts))
     }
 
-    /// Check if a probe exists for this aquarium
-    /// - If query has both probe_type and name: checks for exact match
-    /// - If query has only probe_type: checks if ANY probe with that type exists
-    fn is_probe_available(&self, query: &str, available_probes: &HashSet<ProbeMetadata>) -> bool {
-        let probe_type = self.extract_tag_value(query, "probe_type");
-        let name = self.extract_tag_value(query, "name");
-
-        match (probe_type, name) {
-            // Both probe_type and name specified - check for exact match
-            (Some(pt), Some(n)) => {
-                let metadata = ProbeMetadata {
-                    probe_type: pt.clone(),
-                    name: n.clone(),
-                };
-                let is_available = available_probes.contains(&metadata);
+    /// Query to run for a widget on this backend, or None if the widget should be left out:
+    /// it has no query for the backend, or its series (judged from the InfluxQL query) is missing
+    fn widget_query<'a>(
+        &self,
+        query: Option<&'a str>,
+        influxql: &str,
+        available_series: Option<&HashSet<SeriesMetadata>>,
+    ) -> Option<&'a str> {
+        let query = query?;
+        self.is_series_available(influxql, None, available_series)
+            .then_some(query)
+    }
 
-                tracing::debug!(
-                    "Checking probe availability: probe_type={}, name={}, available={}",
-                    pt, n, is_available
-                );
+    /// Members of a series group to show: the group has a query for this backend, and
+    /// each member's tag value has data (judged from the group's InfluxQL query)
+    fn group_members<'g>(
+        &self,
+        group: &'g SeriesGroupConfig,
+        available_series: Option<&HashSet<SeriesMetadata>>,
+    ) -> Vec<&'g GroupedSeriesConfig> {
+        if group.query_for(self.backend).is_none() {
+            return Vec::new();
+        }
 
-                is_available
-            }
-            // Only probe_type specified - check if ANY probe with this type exists
-            (Some(pt), None) => {
-                let is_available = available_probes.iter().any(|p| p.probe_type == pt);
+        group
+            .series
+            .iter()
+            .filter(|m| {
+                self.is_series_available(&group.query, Some((&group.tag, m.tag_value())), available_series)
+            })
+            .collect()
+    }
 
-                tracing::debug!(
-                    "Checking probe type availability: probe_type={}, available={}",
-                    pt, is_available
-                );
+    /// Check if the widget's series exists for this aquarium: some discovered series of the
+    /// query's measurement satisfies its tag predicates. Measurements that aren't discovered,
+    /// queries the filter parser can't analyze (reported at startup) and a failed discovery
+    /// (`available_series` is None) fail open.
+    /// `member` further requires a tag value, for one series of a grouped query.
+    fn is_series_available(
+        &self,
+        query: &str,
+        member: Option<(&str, &str)>,
+        available_series: Option<&HashSet<SeriesMetadata>>,
+    ) -> bool {
+        // Flux- and SQL-only widgets have no InfluxQL to inspect
+        let Some(available_series) = available_series.filter(|_| !query.is_empty()) else {
+            return true;
+        };
+
+        let filter = match influxql_filter::parse(query) {
+            Ok(filter) => filter,
+            Err(_) => return true,
+        };
+        if !is_discovered(&filter.measurement) {
+            return true;
+        }
 
-                is_available
-            }
-            // No probe_type found - fail open (include the widget)
-            _ => {
-                tracing::warn!("Could not extract probe_type from query: {}", query);
-                true
+        let is_available = available_series.iter().any(|series| {
+            series.measurement == filter.measurement
+                && filter.condition.matches(&|key: &str| series.tag(key))
+                && member.is_none_or(|(tag, value)| series.tag(tag).is_none_or(|v| v == value))
+        });
+
+        tracing::debug!(
+            "Checking series availability: query={}, available={}",
+            query, is_available
+        );
+
+        is_available
+    }
+
+    /// Template variables shared by every widget query in a stream
+    fn query_vars(&self, aquarium_id: &str, range: &TimeRange, points: usize) -> HashMap<String, String> {
+        let mut vars = self.backend.range_vars(range, points);
+        vars.insert("source".to_string(), self.backend.escape_string(aquarium_id));
+        vars
+    }
+}
+
+/// Keeps a dashboard stream's tasks alive; dropping it aborts any queries still in flight.
+/// Hold it for as long as the receiver is being consumed.
+pub struct StreamGuard(AbortHandle);
+
+impl StreamGuard {
+    fn new(handle: AbortHandle) -> Self {
+        Self(handle)
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Wait for every widget task and tally the outcomes of `widget_count` widgets
+async fn collect_outcomes(mut tasks: JoinSet<StreamSummary>, widget_count: usize) -> StreamSummary {
+    let mut summary = StreamSummary::default();
+    while let Some(result) = tasks.join_next().await {
+        if let Ok(task_summary) = result {
+            summary.merge(task_summary);
+        }
+    }
+    // Widgets of panicked or cancelled tasks never reported, so they count as failed
+    summary.failed += widget_count.saturating_sub(summary.total());
+    summary
+}
+
+/// Run a task covering `widgets` widgets, counting them all as failed if it exceeds `WIDGET_DEADLINE`
+async fn with_deadline<F, T>(label: String, widgets: usize, task: F) -> StreamSummary
+where
+    F: Future<Output = T>,
+    T: Into<StreamSummary>,
+{
+    match tokio::time::timeout(WIDGET_DEADLINE, task).await {
+        Ok(outcome) => outcome.into(),
+        Err(_) => {
+            tracing::warn!("Widget {} exceeded the {:?} deadline", label, WIDGET_DEADLINE);
+            StreamSummary {
+                failed: widgets,
+                ..StreamSummary::default()
             }
         }
     }
+}
+
+fn to_sd_points(points: Vec<TimeSeriesPoint>) -> Vec<SDPoint> {
+    points
+        .into_iter()
+        .map(|p| SDPoint::new(Some(p.time_ms), Some(OrderedFloat::from(p.value))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::caching_repository::CacheTtls;
+    use async_trait::async_trait;
+
+    /// Backend with no series at all, whose series discovery may fail
+    struct EmptyRepository {
+        discovery_fails: bool,
+    }
 
-    /// Extract tag value from InfluxQL query
-    /// Example: extract_tag_value(query, "probe_type") from "probe_type"='temp'
-    fn extract_tag_value(&self, query: &str, tag_name: &str) -> Option<String> {
-        let pattern = format!("\"{}\"='", tag_name);
-        if let Some(start) = query.find(&pattern) {
-            let start_idx = start + pattern.len();
-            if let Some(end_idx) = query[start_idx..].find('\'') {
-                return Some(query[start_idx..start_idx + end_idx].to_string());
+    #[async_trait]
+    impl TelemetryRepository for EmptyRepository {
+        async fn list_aquarium_ids(&self) -> anyhow::Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        async fn get_series_metadata(&self, _aquarium_id: &str, _range: &TimeRange) -> anyhow::Result<Vec<SeriesMetadata>> {
+            if self.discovery_fails {
+                anyhow::bail!("InfluxDB series metadata query for apex_probe failed: timeout");
             }
+            Ok(Vec::new())
+        }
+
+        async fn query_single_value(&self, _query: &str) -> anyhow::Result<Option<f64>> {
+            Ok(None)
+        }
+
+        async fn query_time_series_downsampled(
+            &self,
+            _query: &str,
+            _max_points: usize,
+            _downsampling: Downsampling,
+        ) -> anyhow::Result<Vec<TimeSeriesPoint>> {
+            Ok(Vec::new())
         }
-        None
     }
 
-    fn prepare_tile_query(&self, query: &str, aquarium_id: &str, hours: i32) -> String {
-        let mut vars = HashMap::new();
-        vars.insert("source".to_string(), aquarium_id.to_string());
-        vars.insert("hours".to_string(), hours.to_string());
-        prepare_query(query, &vars)
+    fn service(discovery_fails: bool) -> StreamingDashboardService {
+        let mut widgets: WidgetsConfig = toml::from_str(
+            r#"
+            [[tiles]]
+            id = "t-temp"
+            title = "Temperature"
+            unit = "°F"
+            precision = 1
+            select = { measurement = "apex_probe", tags = { probe_type = "temp", name = "Tmp" } }
+
+            [[pages]]
+            id = "probes"
+            title = "Probes"
+            tiles = ["t-temp"]
+
+            [[pages]]
+            id = "equipment"
+            title = "Equipment"
+            charts = ["c-atk"]
+            "#,
+        )
+        .unwrap();
+        widgets.compile_queries();
+        let ttls = CacheTtls {
+            single_value: Duration::ZERO,
+            time_series: Duration::ZERO,
+            series_metadata: Duration::ZERO,
+            aquarium_ids: Duration::ZERO,
+        };
+        StreamingDashboardService::new(
+            Arc::new(CachingRepository::new(Arc::new(EmptyRepository { discovery_fails }), ttls, 0)),
+            Arc::new(QueryScheduler::new(4, 2)),
+            Arc::new(ConfigHandle::new(widgets)),
+            Backend::InfluxQL,
+        )
     }
 
-    fn prepare_chart_query(&self, query: &str, aquarium_id: &str, hours: i32) -> String {
-        self.prepare_tile_query(query, aquarium_id, hours)
+    async fn skeleton_tiles(discovery_fails: bool) -> usize {
+        let (mut rx, _guard) = service(discovery_fails)
+            .stream_dashboard("Planet_72", None, &TimeRange::Last { hours: 6 }, 150)
+            .await
+            .unwrap();
+        let skeleton = rx.recv().await.unwrap().skeleton.unwrap();
+        skeleton.tiles.unwrap_or_default().len()
+    }
+
+    #[tokio::test]
+    async fn test_failed_discovery_shows_every_widget() {
+        // A series that wasn't discovered is left out, but a failed discovery hides nothing
+        assert_eq!(skeleton_tiles(false).await, 0);
+        assert_eq!(skeleton_tiles(true).await, 1);
     }
-}
 
+    #[tokio::test]
+    async fn test_only_listed_pages_are_streamed() {
+        let service = service(true);
+        let range = TimeRange::Last { hours: 6 };
+
+        assert!(service.stream_dashboard("Planet_72", Some("probes"), &range, 150).await.is_some());
+        // A page without any configured widget isn't listed, so it isn't streamed either
+        assert!(service.stream_dashboard("Planet_72", Some("equipment"), &range, 150).await.is_none());
+        assert!(service.stream_dashboard("Planet_72", Some("missing"), &range, 150).await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_collect_outcomes_counts_each_widget() {
+        let mut tasks = JoinSet::new();
+        tasks.spawn(with_deadline("tiles".to_string(), 2, async {
+            let mut summary = StreamSummary::from(WidgetOutcome::Delivered);
+            summary.record(WidgetOutcome::Empty);
+            summary
+        }));
+        tasks.spawn(with_deadline("s-temp".to_string(), 1, async { WidgetOutcome::Delivered }));
+        tasks.spawn(with_deadline("s-slow".to_string(), 1, std::future::pending::<WidgetOutcome>()));
+        tasks.spawn(async { panic!("widget task panicked") });
+
+        let summary = collect_outcomes(tasks, 5).await;
+
+        assert_eq!(
+            summary,
+            StreamSummary {
+                delivered: 2,
+                empty: 1,
+                failed: 2,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropping_guard_aborts_widget_tasks() {
+        let (probe_tx, probe_rx) = tokio::sync::oneshot::channel::<()>();
+        let mut tasks = JoinSet::new();
+        tasks.spawn(async move {
+            let _probe = probe_tx;
+            std::future::pending::<StreamSummary>().await
+        });
+        let guard = StreamGuard::new(tokio::spawn(collect_outcomes(tasks, 1)).abort_handle());
+
+        drop(guard);
+
+        // The widget task was aborted, so its end of the channel is gone
+        assert!(probe_rx.await.is_err());
+    }
+}
@@ -1,12 +1,55 @@
 // Repository trait for telemetry data access
-use crate::domain::telemetry::TimeSeriesPoint;
+use crate::domain::downsampling::Downsampling;
+use crate::domain::telemetry::{TaggedSeries, TimeSeriesPoint};
+use crate::domain::time_range::TimeRange;
 use async_trait::async_trait;
+use futures::future::join_all;
+use std::collections::BTreeMap;
 
-/// Metadata about a probe (probe_type and name)
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct ProbeMetadata {
-    pub probe_type: String,
-    pub name: String,
+/// Measurements whose series are discovered per aquarium, with the tags that identify a series
+pub const DISCOVERED_MEASUREMENTS: [(&str, &[&str]); 3] = [
+    ("apex_probe", &["probe_type", "name"]),
+    ("apex_cor", &["name"]),
+    ("apex_output", &["name"]),
+];
+
+/// A series that has data for an aquarium: its measurement and identifying tags
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SeriesMetadata {
+    pub measurement: String,
+    pub tags: BTreeMap<String, String>,
+}
+
+impl SeriesMetadata {
+    /// Keep the identifying tags of a discovered measurement, looked up with `tag`.
+    /// None if the measurement isn't discovered or an identifying tag is missing or empty.
+    pub fn from_tags<'a>(measurement: &str, tag: impl Fn(&str) -> Option<&'a str>) -> Option<Self> {
+        let (_, keys) = DISCOVERED_MEASUREMENTS
+            .iter()
+            .find(|(discovered, _)| *discovered == measurement)?;
+
+        let tags = keys
+            .iter()
+            .map(|key| match tag(key) {
+                Some(value) if !value.is_empty() => Some((key.to_string(), value.to_string())),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Self {
+            measurement: measurement.to_string(),
+            tags,
+        })
+    }
+
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+}
+
+/// Whether `measurement` is one whose series are discovered
+pub fn is_discovered(measurement: &str) -> bool {
+    DISCOVERED_MEASUREMENTS.iter().any(|(discovered, _)| *discovered == measurement)
 }
 
 #[async_trait]
@@ -14,18 +57,41 @@ pub trait TelemetryRepository: Send + Sync {
     /// List all available aquarium IDs
     async fn list_aquarium_ids(&self) -> anyhow::Result<Vec<String>>;
 
-    /// Get all probe metadata (probe_type, name) for an aquarium in a single query
-    /// Filters based on the selected time range (hours)
-    async fn get_probe_metadata(&self, aquarium_id: &str, hours: i32) -> anyhow::Result<Vec<ProbeMetadata>>;
+    /// Get the series of every discovered measurement that have data for an aquarium
+    /// within the selected time range
+    async fn get_series_metadata(&self, aquarium_id: &str, range: &TimeRange) -> anyhow::Result<Vec<SeriesMetadata>>;
 
     /// Query a single value (for tiles)
     async fn query_single_value(&self, query: &str) -> anyhow::Result<Option<f64>>;
 
+    /// Query several single values at once, returning one result per query in the same order.
+    /// The outer error means the whole batch failed. The default runs the queries concurrently;
+    /// backends that can batch statements into one round trip should override it.
+    async fn query_single_values(
+        &self,
+        queries: &[String],
+    ) -> anyhow::Result<Vec<anyhow::Result<Option<f64>>>> {
+        Ok(join_all(queries.iter().map(|q| self.query_single_value(q))).await)
+    }
+
     /// Query time series data (for charts) with server-side downsampling
     async fn query_time_series_downsampled(
         &self,
         query: &str,
         max_points: usize,
+        downsampling: Downsampling,
     ) -> anyhow::Result<Vec<TimeSeriesPoint>>;
+
+    /// Query a grouped time series (e.g. `GROUP BY "name"`), returning one downsampled series
+    /// per tag combination. Backends that serve series groups must override it; the default
+    /// fails rather than hand the group a series none of its members can claim.
+    async fn query_tagged_series(
+        &self,
+        query: &str,
+        _max_points: usize,
+        _downsampling: Downsampling,
+    ) -> anyhow::Result<Vec<TaggedSeries>> {
+        anyhow::bail!("Grouped queries are not supported by this repository: {}", query)
+    }
 }
 
@@ -0,0 +1,181 @@
+// Downsampling strategies for time series, shared by every telemetry backend
+use super::telemetry::TimeSeriesPoint;
+use serde::Deserialize;
+
+/// Points per series when the client doesn't ask for a budget
+pub const DEFAULT_POINTS_PER_SERIES: usize = 150;
+
+/// Server limits on a client-requested budget
+pub const MIN_POINTS_PER_SERIES: usize = 10;
+pub const MAX_POINTS_PER_SERIES: usize = 1000;
+
+/// Points per series for a requested budget, clamped to the server limits
+pub fn point_budget(requested: Option<usize>) -> usize {
+    requested
+        .unwrap_or(DEFAULT_POINTS_PER_SERIES)
+        .clamp(MIN_POINTS_PER_SERIES, MAX_POINTS_PER_SERIES)
+}
+
+/// How a series longer than the point budget is reduced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Downsampling {
+    /// Average of fixed-size buckets; smooth, but flattens spikes
+    #[default]
+    Average,
+    /// Largest-Triangle-Three-Buckets; keeps the visual shape of the series
+    Lttb,
+    /// Minimum and maximum of each bucket, keeping the full envelope
+    MinMax,
+}
+
+impl Downsampling {
+    /// Reduce `points` (sorted by time) to at most `max_points`
+    pub fn apply(self, points: Vec<TimeSeriesPoint>, max_points: usize) -> Vec<TimeSeriesPoint> {
+        if points.len() <= max_points {
+            return points;
+        }
+
+        match self {
+            Downsampling::Average => average(points, max_points),
+            Downsampling::Lttb => lttb(points, max_points),
+            Downsampling::MinMax => min_max(points, max_points),
+        }
+    }
+}
+
+fn average(points: Vec<TimeSeriesPoint>, max_points: usize) -> Vec<TimeSeriesPoint> {
+    let bucket_size = points.len().div_ceil(max_points.max(1));
+
+    points
+        .chunks(bucket_size)
+        .map(|chunk| {
+            // Use middle point's timestamp and average value
+            let avg_value = chunk.iter().map(|p| p.value).sum::<f64>() / chunk.len() as f64;
+            TimeSeriesPoint::new(chunk[chunk.len() / 2].time_ms, avg_value)
+        })
+        .collect()
+}
+
+fn lttb(points: Vec<TimeSeriesPoint>, max_points: usize) -> Vec<TimeSeriesPoint> {
+    // First and last points are always kept, so fewer than 3 leaves nothing to choose
+    if max_points < 3 {
+        return average(points, max_points);
+    }
+
+    let n = points.len();
+    let bucket_width = (n - 2) as f64 / (max_points - 2) as f64;
+    let bucket_bounds = |i: usize| {
+        let start = (i as f64 * bucket_width) as usize + 1;
+        let end = (((i + 1) as f64 * bucket_width) as usize + 1).min(n - 1);
+        (start, end)
+    };
+
+    let mut sampled = Vec::with_capacity(max_points);
+    sampled.push(points[0].clone());
+    let mut selected = 0;
+
+    for i in 0..max_points - 2 {
+        let (start, end) = bucket_bounds(i);
+
+        // Average of the next bucket (or the last point) is the third triangle vertex
+        let (next_start, next_end) = if i + 1 < max_points - 2 {
+            bucket_bounds(i + 1)
+        } else {
+            (n - 1, n)
+        };
+        let next = &points[next_start..next_end];
+        let avg_time = next.iter().map(|p| p.time_ms as f64).sum::<f64>() / next.len() as f64;
+        let avg_value = next.iter().map(|p| p.value).sum::<f64>() / next.len() as f64;
+
+        let a = &points[selected];
+        let (a_time, a_value) = (a.time_ms as f64, a.value);
+        let area = |p: &TimeSeriesPoint| {
+            ((a_time - avg_time) * (p.value - a_value)
+                - (a_time - p.time_ms as f64) * (avg_value - a_value))
+                .abs()
+        };
+
+        selected = (start..end)
+            .max_by(|&x, &y| area(&points[x]).total_cmp(&area(&points[y])))
+            .unwrap_or(start);
+        sampled.push(points[selected].clone());
+    }
+
+    sampled.push(points[n - 1].clone());
+    sampled
+}
+
+fn min_max(points: Vec<TimeSeriesPoint>, max_points: usize) -> Vec<TimeSeriesPoint> {
+    // Two points per bucket
+    let bucket_size = points.len().div_ceil((max_points / 2).max(1));
+    let mut sampled = Vec::with_capacity(max_points);
+
+    for chunk in points.chunks(bucket_size) {
+        let min = chunk.iter().min_by(|a, b| a.value.total_cmp(&b.value)).unwrap();
+        let max = chunk.iter().max_by(|a, b| a.value.total_cmp(&b.value)).unwrap();
+
+        // Emit in time order so the line doesn't double back
+        if min.time_ms <= max.time_ms {
+            sampled.push(min.clone());
+            if max.time_ms != min.time_ms {
+                sampled.push(max.clone());
+            }
+        } else {
+            sampled.push(max.clone());
+            sampled.push(min.clone());
+        }
+    }
+
+    sampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flat series with a single spike at `spike_at`
+    fn spiky(len: usize, spike_at: usize) -> Vec<TimeSeriesPoint> {
+        (0..len)
+            .map(|i| TimeSeriesPoint::new(i as i64 * 60_000, if i == spike_at { 9.0 } else { 8.0 }))
+            .collect()
+    }
+
+    #[test]
+    fn test_point_budget_is_clamped() {
+        assert_eq!(point_budget(None), DEFAULT_POINTS_PER_SERIES);
+        assert_eq!(point_budget(Some(400)), 400);
+        assert_eq!(point_budget(Some(0)), MIN_POINTS_PER_SERIES);
+        assert_eq!(point_budget(Some(1_000_000)), MAX_POINTS_PER_SERIES);
+    }
+
+    #[test]
+    fn test_short_series_is_unchanged() {
+        for strategy in [Downsampling::Average, Downsampling::Lttb, Downsampling::MinMax] {
+            assert_eq!(strategy.apply(spiky(10, 3), 150).len(), 10);
+        }
+    }
+
+    #[test]
+    fn test_lttb_keeps_spike_and_endpoints() {
+        let sampled = Downsampling::Lttb.apply(spiky(1000, 517), 50);
+
+        assert_eq!(sampled.len(), 50);
+        assert_eq!(sampled[0].time_ms, 0);
+        assert_eq!(sampled[49].time_ms, 999 * 60_000);
+        assert!(sampled.iter().any(|p| p.value == 9.0));
+        assert!(sampled.windows(2).all(|w| w[0].time_ms < w[1].time_ms));
+    }
+
+    #[test]
+    fn test_min_max_keeps_spike_where_average_flattens_it() {
+        let min_max = Downsampling::MinMax.apply(spiky(1000, 517), 50);
+        assert!(min_max.len() <= 50);
+        assert!(min_max.iter().any(|p| p.value == 9.0));
+        assert!(min_max.windows(2).all(|w| w[0].time_ms < w[1].time_ms));
+
+        let average = Downsampling::Average.apply(spiky(1000, 517), 50);
+        assert_eq!(average.len(), 50);
+        assert!(average.iter().all(|p| p.value < 9.0));
+    }
+}
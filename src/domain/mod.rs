@@ -1,5 +1,7 @@
 // Domain layer - Core business logic and models
 pub mod aquarium;
 pub mod dashboard;
+pub mod downsampling;
 pub mod telemetry;
+pub mod time_range;
 
@@ -1,4 +1,5 @@
 // Telemetry data domain models
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone)]
 pub struct TimeSeriesPoint {
@@ -12,6 +13,23 @@ impl TimeSeriesPoint {
     }
 }
 
+/// One series of a grouped query result, identified by its tag values
+#[derive(Debug, Clone)]
+pub struct TaggedSeries {
+    pub tags: BTreeMap<String, String>,
+    pub points: Vec<TimeSeriesPoint>,
+}
+
+impl TaggedSeries {
+    pub fn new(tags: BTreeMap<String, String>, points: Vec<TimeSeriesPoint>) -> Self {
+        Self { tags, points }
+    }
+
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TileData {
     pub id: String,
@@ -0,0 +1,170 @@
+// Time window a dashboard is drawn for
+
+/// Default window when the client asks for neither `hours` nor `start`
+pub const DEFAULT_HOURS: i32 = 6;
+
+/// Longest range a dashboard can be drawn for (about ten years)
+pub const MAX_HOURS: i32 = 10 * 366 * 24;
+
+/// Standard GROUP BY bucket sizes, in seconds
+const INTERVAL_STEPS_SECS: [i64; 13] = [
+    10, 30, 60, 120, 300, 600, 900, 1800, 3600, 3 * 3600, 6 * 3600, 12 * 3600, 86400,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeRange {
+    /// The last `hours` hours up to now; queries stay relative so their text is stable
+    Last { hours: i32 },
+    /// A fixed window in epoch milliseconds
+    Between { start_ms: i64, end_ms: i64 },
+}
+
+impl TimeRange {
+    /// Build a range from request parameters. `start`/`end` take precedence over
+    /// `hours`; a missing `end` means now.
+    pub fn from_params(
+        hours: Option<i32>,
+        start: Option<&str>,
+        end: Option<&str>,
+        now_ms: i64,
+    ) -> anyhow::Result<Self> {
+        match (start, end) {
+            (None, None) => {
+                let hours = hours.unwrap_or(DEFAULT_HOURS);
+                anyhow::ensure!(hours > 0, "hours must be positive");
+                anyhow::ensure!(hours <= MAX_HOURS, "hours must be at most {}", MAX_HOURS);
+                Ok(TimeRange::Last { hours })
+            }
+            (None, Some(_)) => anyhow::bail!("end requires start"),
+            (Some(start), end) => {
+                let start_ms = parse_timestamp(start)?;
+                let end_ms = end.map(parse_timestamp).transpose()?.unwrap_or(now_ms);
+                anyhow::ensure!(start_ms < end_ms, "start must be before end");
+                anyhow::ensure!(
+                    end_ms - start_ms <= MAX_HOURS as i64 * 3_600_000,
+                    "range must be at most {} hours",
+                    MAX_HOURS
+                );
+                Ok(TimeRange::Between { start_ms, end_ms })
+            }
+        }
+    }
+
+    pub fn duration_ms(&self) -> i64 {
+        match *self {
+            TimeRange::Last { hours } => hours as i64 * 3_600_000,
+            TimeRange::Between { start_ms, end_ms } => end_ms.saturating_sub(start_ms),
+        }
+    }
+
+    /// GROUP BY bucket for a chart of `points` points: the largest standard bucket
+    /// that still gives at least `points` buckets, so the database aggregates as much
+    /// as it can without the chart losing resolution
+    pub fn group_interval_secs(&self, points: usize) -> i64 {
+        let per_point = self.duration_ms() / 1000 / points.max(1) as i64;
+        INTERVAL_STEPS_SECS
+            .iter()
+            .rev()
+            .copied()
+            .find(|&step| step <= per_point)
+            .unwrap_or(INTERVAL_STEPS_SECS[0])
+    }
+
+    /// Length of the range rounded up to whole hours
+    pub fn hours(&self) -> i32 {
+        match *self {
+            TimeRange::Last { hours } => hours,
+            TimeRange::Between { .. } => {
+                let hours = (self.duration_ms().max(0) as u64).div_ceil(3_600_000);
+                i32::try_from(hours).unwrap_or(i32::MAX)
+            }
+        }
+    }
+}
+
+/// Parse an RFC 3339 timestamp or epoch milliseconds within the range chrono can represent
+fn parse_timestamp(value: &str) -> anyhow::Result<i64> {
+    if let Ok(ms) = value.parse::<i64>() {
+        anyhow::ensure!(
+            chrono::DateTime::from_timestamp_millis(ms).is_some(),
+            "timestamp {} is out of range",
+            ms
+        );
+        return Ok(ms);
+    }
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|t| t.timestamp_millis())
+        .map_err(|_| anyhow::anyhow!("invalid timestamp {:?}, expected RFC 3339 or epoch milliseconds", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW_MS: i64 = 1_735_689_600_000;
+
+    #[test]
+    fn test_relative_range() {
+        assert_eq!(
+            TimeRange::from_params(None, None, None, NOW_MS).unwrap(),
+            TimeRange::Last { hours: DEFAULT_HOURS }
+        );
+        assert_eq!(
+            TimeRange::from_params(Some(24), None, None, NOW_MS).unwrap(),
+            TimeRange::Last { hours: 24 }
+        );
+        assert!(TimeRange::from_params(Some(0), None, None, NOW_MS).is_err());
+    }
+
+    #[test]
+    fn test_absolute_range() {
+        let range = TimeRange::from_params(
+            Some(6),
+            Some("2024-12-31T23:40:00Z"),
+            Some("1735689600000"),
+            NOW_MS,
+        )
+        .unwrap();
+        assert_eq!(
+            range,
+            TimeRange::Between {
+                start_ms: NOW_MS - 20 * 60_000,
+                end_ms: NOW_MS,
+            }
+        );
+        assert_eq!(range.hours(), 1);
+
+        // Open-ended ranges run until now
+        let open = TimeRange::from_params(None, Some("1735686000000"), None, NOW_MS).unwrap();
+        assert_eq!(open.duration_ms(), 3_600_000);
+    }
+
+    #[test]
+    fn test_group_interval() {
+        // 6h over 150 points is 144s per point
+        assert_eq!(TimeRange::Last { hours: 6 }.group_interval_secs(150), 120);
+        assert_eq!(TimeRange::Last { hours: 24 * 30 }.group_interval_secs(150), 3 * 3600);
+        assert_eq!(TimeRange::Last { hours: 1 }.group_interval_secs(1000), 10);
+    }
+
+    #[test]
+    fn test_invalid_ranges() {
+        assert!(TimeRange::from_params(None, None, Some("1735689600000"), NOW_MS).is_err());
+        assert!(TimeRange::from_params(None, Some("yesterday"), None, NOW_MS).is_err());
+        assert!(TimeRange::from_params(None, Some("1735689600000"), Some("1735686000000"), NOW_MS).is_err());
+    }
+
+    #[test]
+    fn test_extreme_ranges_are_rejected() {
+        let (min, max) = (i64::MIN.to_string(), i64::MAX.to_string());
+        assert!(TimeRange::from_params(None, Some(&min), Some(&max), NOW_MS).is_err());
+        assert!(TimeRange::from_params(None, Some(&min), None, NOW_MS).is_err());
+        assert!(TimeRange::from_params(None, Some("-8000000000000000"), Some("8000000000000000"), NOW_MS).is_err());
+        assert!(TimeRange::from_params(Some(i32::MAX), None, None, NOW_MS).is_err());
+
+        let longest = TimeRange::from_params(Some(MAX_HOURS), None, None, NOW_MS).unwrap();
+        assert_eq!(longest.hours(), MAX_HOURS);
+        let start = (NOW_MS - MAX_HOURS as i64 * 3_600_000).to_string();
+        assert_eq!(TimeRange::from_params(None, Some(&start), None, NOW_MS).unwrap().hours(), MAX_HOURS);
+    }
+}
@@ -70,11 +70,19 @@ async fn serialize_chunk(msg: StreamMessage, compress: bool) -> Result<Bytes, st
 }
 
 /// Helper to create a streaming response from a receiver
-pub async fn stream_from_receiver(
+///
+/// `guard` is moved into the response body and dropped with it, so anything it
+/// owns (e.g. the producer tasks) lives exactly as long as the client connection.
+pub async fn stream_from_receiver<G>(
     mut rx: tokio::sync::mpsc::Receiver<StreamMessage>,
+    guard: G,
     compress: bool,
-) -> impl IntoResponse {
+) -> impl IntoResponse
+where
+    G: Send + 'static,
+{
     let stream = async_stream::stream! {
+        let _guard = guard;
         while let Some(msg) = rx.recv().await {
             yield msg;
         }
@@ -0,0 +1,150 @@
+// CLI - Startup options from command line flags and AQ_ environment variables
+use crate::infrastructure::config::{INFLUX_SETTINGS_FIELDS, WIDGETS_CONFIG_FILE};
+use anyhow::{bail, Context};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+const DEFAULT_LISTEN: &str = "0.0.0.0:8080";
+const DEFAULT_CONFIG_DIR: &str = "config";
+
+/// Flags besides the `--influx-<field>` ones
+const FLAGS: [&str; 3] = ["listen", "config-dir", "fixtures"];
+
+/// Settings only taken from the environment: as a flag, the value would show in the process list
+const ENV_ONLY: [&str; 1] = ["influx-token"];
+
+/// Each option is a flag, `--name value` or `--name=value`, or else the `AQ_NAME`
+/// environment variable (`--config-dir` is `AQ_CONFIG_DIR`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Address the HTTP server listens on (`--listen`)
+    pub listen: SocketAddr,
+    /// Directory holding influx.toml and widgets.toml (`--config-dir`)
+    pub config_dir: String,
+    /// Fixture dataset served instead of InfluxDB (`--fixtures`)
+    pub fixtures: Option<String>,
+    /// `InfluxSettings` fields set with `--influx-<field>` (e.g. `--influx-token-file`),
+    /// which take precedence over influx.toml. The token itself is only read from `AQ_INFLUX_TOKEN`.
+    pub influx: Vec<(String, String)>,
+}
+
+impl Options {
+    /// Parse `args` (without the program name), falling back to `env` for options not given
+    pub fn parse(
+        args: impl IntoIterator<Item = String>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<Self> {
+        let mut flags: HashMap<String, String> = HashMap::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                bail!("Unexpected argument {:?}", arg);
+            };
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (flag, None),
+            };
+            if ENV_ONLY.contains(&name) {
+                bail!(
+                    "--{} would expose the secret in the process list; set {} or use --influx-token-file",
+                    name,
+                    env_var(name)
+                );
+            }
+            if !FLAGS.contains(&name) && influx_field(name).is_none() {
+                bail!("Unknown flag --{}", name);
+            }
+            let value = match value {
+                Some(value) => value,
+                None => args.next().with_context(|| format!("--{} needs a value", name))?,
+            };
+            flags.insert(name.to_string(), value);
+        }
+
+        let value = |name: &str| flags.get(name).cloned().or_else(|| env(&env_var(name)));
+        let listen = value("listen").unwrap_or_else(|| DEFAULT_LISTEN.to_string());
+        let influx = INFLUX_SETTINGS_FIELDS
+            .iter()
+            .filter_map(|field| {
+                let value = value(&format!("influx-{}", field.replace('_', "-")))?;
+                Some((field.to_string(), value))
+            })
+            .collect();
+
+        Ok(Self {
+            listen: listen
+                .parse()
+                .with_context(|| format!("Invalid listen address {:?}", listen))?,
+            config_dir: value("config-dir").unwrap_or_else(|| DEFAULT_CONFIG_DIR.to_string()),
+            fixtures: value("fixtures"),
+            influx,
+        })
+    }
+
+    pub fn widgets_path(&self) -> String {
+        format!("{}/{}", self.config_dir, WIDGETS_CONFIG_FILE)
+    }
+}
+
+/// `InfluxSettings` field of an `influx-<field>` flag
+fn influx_field(flag: &str) -> Option<&'static str> {
+    let field = flag.strip_prefix("influx-")?.replace('-', "_");
+    INFLUX_SETTINGS_FIELDS.iter().copied().find(|f| *f == field)
+}
+
+/// Environment variable of a flag: `config-dir` is `AQ_CONFIG_DIR`
+fn env_var(flag: &str) -> String {
+    format!("AQ_{}", flag.replace('-', "_").to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str], env: &[(&str, &str)]) -> anyhow::Result<Options> {
+        let env: HashMap<String, String> = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Options::parse(args.iter().map(|a| a.to_string()), |name| env.get(name).cloned())
+    }
+
+    #[test]
+    fn test_flags_take_precedence_over_environment() {
+        let options = parse(
+            &["--listen", "127.0.0.1:9000", "--influx-token-file=/run/credentials/influx-token"],
+            &[
+                ("AQ_LISTEN", "0.0.0.0:80"),
+                ("AQ_CONFIG_DIR", "/etc/aquarium"),
+                ("AQ_INFLUX_TOKEN_FILE", "/run/secrets/token"),
+                ("AQ_INFLUX_REQUEST_TIMEOUT_MS", "5000"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(options.listen, "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(options.config_dir, "/etc/aquarium");
+        assert_eq!(options.widgets_path(), "/etc/aquarium/widgets.toml");
+        assert_eq!(options.fixtures, None);
+        assert_eq!(
+            options.influx,
+            [
+                ("token_file".to_string(), "/run/credentials/influx-token".to_string()),
+                ("request_timeout_ms".to_string(), "5000".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_defaults_and_bad_arguments() {
+        let options = parse(&[], &[]).unwrap();
+        assert_eq!(options.listen, DEFAULT_LISTEN.parse().unwrap());
+        assert_eq!(options.config_dir, DEFAULT_CONFIG_DIR);
+        assert!(options.influx.is_empty());
+
+        assert!(parse(&["--influx-tokn", "x"], &[]).is_err());
+        assert!(parse(&["--influx-token=secret"], &[]).is_err());
+        let options = parse(&[], &[("AQ_INFLUX_TOKEN", "secret")]).unwrap();
+        assert_eq!(options.influx, [("token".to_string(), "secret".to_string())]);
+        assert!(parse(&["--fixtures"], &[]).is_err());
+        assert!(parse(&["fixtures.toml"], &[]).is_err());
+        assert!(parse(&[], &[("AQ_LISTEN", "localhost")]).is_err());
+    }
+}
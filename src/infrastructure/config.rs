@@ -1,25 +1,416 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+use crate::application::telemetry_repository::SeriesMetadata;
+use crate::domain::downsampling::Downsampling;
+use crate::domain::time_range::TimeRange;
+use crate::infrastructure::config_check::{check_widgets_file, Severity};
+use crate::infrastructure::query_builder::QuerySpec;
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Widget configuration file in the config directory, reloaded when it changes
+pub const WIDGETS_CONFIG_FILE: &str = "widgets.toml";
+
+/// The shipped widget configuration, relative to the crate root
+#[cfg(test)]
+pub const WIDGETS_CONFIG_PATH: &str = "config/widgets.toml";
+
+/// Every `${name}` a widget query can use: `source` plus the `range_vars`
+pub const TEMPLATE_VARIABLES: [&str; 6] = ["source", "start", "end", "range", "hours", "interval"];
+
+/// Colors given to template-generated series, in order
+const SERIES_PALETTE: [&str; 8] = [
+    "#007aff", "#ff9500", "#34c759", "#af52de", "#ff2d55", "#5ac8fa", "#ffcc00", "#ff6b6b",
+];
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct InfluxConfig {
     pub influx: InfluxSettings,
+    #[serde(default)]
+    pub scheduler: SchedulerSettings,
+    #[serde(default)]
+    pub cache: CacheSettings,
 }
 
+/// `InfluxSettings` fields, which flags and environment variables can also set
+pub const INFLUX_SETTINGS_FIELDS: [&str; 12] = [
+    "host",
+    "token",
+    "token_file",
+    "database",
+    "retention_policy",
+    "backend",
+    "org",
+    "bucket",
+    "connect_timeout_ms",
+    "request_timeout_ms",
+    "max_retries",
+    "retry_backoff_ms",
+];
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct InfluxSettings {
     pub host: String,
+    /// API token; exactly one of `token` and `token_file` must be set
+    #[serde(default)]
     pub token: String,
+    /// File holding the token (e.g. a Docker secret or systemd credential), read in place of `token`
+    pub token_file: Option<String>,
     pub database: String,
     pub retention_policy: String,
+    /// Query backend: "influxql" (v1 /query), "flux" (v2 /api/v2/query) or "sql" (v3 /api/v3/query_sql)
+    #[serde(default)]
+    pub backend: Backend,
+    /// Organization for the v2 query API (Flux)
+    pub org: Option<String>,
+    /// Bucket for Flux queries; defaults to "<database>/<retention_policy>"
+    pub bucket: Option<String>,
+    /// TCP/TLS connect timeout for InfluxDB requests
+    #[serde(default = "default_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+    /// Timeout for a whole InfluxDB request, including reading the response
+    #[serde(default = "default_request_timeout_ms")]
+    pub request_timeout_ms: u64,
+    /// Retries for connection failures, 429 and 5xx responses
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Base delay for exponential retry backoff (jittered)
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+}
+
+impl InfluxSettings {
+    /// Bucket for Flux queries; InfluxDB maps v1 database/retention policy pairs to "db/rp"
+    pub fn bucket(&self) -> String {
+        self.bucket
+            .clone()
+            .unwrap_or_else(|| format!("{}/{}", self.database, self.retention_policy))
+    }
+}
+
+/// Query language of the deployment's backend; widgets carry one query per language
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    InfluxQL,
+    Flux,
+    Sql,
+}
+
+impl Backend {
+    /// Pick the widget query written for this backend
+    fn select<'a>(self, influxql: &'a str, flux: Option<&'a str>, sql: Option<&'a str>) -> Option<&'a str> {
+        match self {
+            Backend::InfluxQL => Some(influxql).filter(|q| !q.is_empty()),
+            Backend::Flux => flux,
+            Backend::Sql => sql,
+        }
+    }
+
+    /// Escape a value for use inside a string literal of this backend's language
+    /// (`'...'` for InfluxQL and SQL, `"..."` for Flux)
+    pub fn escape_string(self, value: &str) -> String {
+        match self {
+            Backend::InfluxQL => value
+                .replace('\\', "\\\\")
+                .replace('\'', "\\'")
+                .replace('\n', "\\n"),
+            // Flux strings also interpolate `${...}`
+            Backend::Flux => value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('$', "\\$")
+                .replace('\n', "\\n"),
+            // Standard SQL: backslashes are literal, quotes are doubled
+            Backend::Sql => value.replace('\'', "''"),
+        }
+    }
+
+    /// `${start}` and `${end}` for a range: time expressions in this backend's language
+    pub fn time_bounds(self, range: &TimeRange) -> (String, String) {
+        match *range {
+            TimeRange::Last { hours } => {
+                let start = match self {
+                    Backend::InfluxQL => format!("now() - {}h", hours),
+                    Backend::Flux => format!("-{}h", hours),
+                    Backend::Sql => format!("now() - INTERVAL '{} hours'", hours),
+                };
+                (start, "now()".to_string())
+            }
+            TimeRange::Between { start_ms, end_ms } => (
+                self.timestamp_literal(start_ms),
+                self.timestamp_literal(end_ms),
+            ),
+        }
+    }
+
+    fn timestamp_literal(self, ms: i64) -> String {
+        // Ranges are checked when parsed; clamp rather than fall back to the epoch
+        let time = chrono::DateTime::from_timestamp_millis(ms).unwrap_or(if ms < 0 {
+            chrono::DateTime::<chrono::Utc>::MIN_UTC
+        } else {
+            chrono::DateTime::<chrono::Utc>::MAX_UTC
+        });
+        let rfc3339 = time
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        match self {
+            Backend::InfluxQL => format!("'{}'", rfc3339),
+            Backend::Flux => rfc3339,
+            Backend::Sql => format!("TIMESTAMP '{}'", rfc3339),
+        }
+    }
+
+    /// `${range}`: the range length as a duration literal
+    fn duration_literal(self, ms: i64) -> String {
+        let secs = (ms / 1000).max(1);
+        match self {
+            Backend::Sql => format!("INTERVAL '{} seconds'", secs),
+            Backend::InfluxQL | Backend::Flux if secs % 3600 == 0 => format!("{}h", secs / 3600),
+            Backend::InfluxQL | Backend::Flux if secs % 60 == 0 => format!("{}m", secs / 60),
+            Backend::InfluxQL | Backend::Flux => format!("{}s", secs),
+        }
+    }
+
+    /// Template variables describing a range: `${start}`, `${end}`, `${range}`,
+    /// `${hours}` (the length rounded up to whole hours) and `${interval}` (the
+    /// GROUP BY bucket for a chart of `points` points)
+    pub fn range_vars(self, range: &TimeRange, points: usize) -> HashMap<String, String> {
+        let (start, end) = self.time_bounds(range);
+        HashMap::from([
+            ("start".to_string(), start),
+            ("end".to_string(), end),
+            ("range".to_string(), self.duration_literal(range.duration_ms())),
+            ("hours".to_string(), range.hours().to_string()),
+            (
+                "interval".to_string(),
+                self.duration_literal(range.group_interval_secs(points) * 1000),
+            ),
+        ])
+    }
+}
+
+fn default_connect_timeout_ms() -> u64 {
+    3_000
 }
 
+fn default_request_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_max_retries() -> u32 {
+    2
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    200
+}
+
+/// Concurrency limits for repository queries
 #[derive(Debug, Deserialize, Clone)]
+pub struct SchedulerSettings {
+    /// Maximum queries in flight across all dashboard streams
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+    /// Maximum queries in flight for a single dashboard stream
+    #[serde(default = "default_max_per_stream")]
+    pub max_per_stream: usize,
+}
+
+impl Default for SchedulerSettings {
+    fn default() -> Self {
+        Self {
+            max_in_flight: default_max_in_flight(),
+            max_per_stream: default_max_per_stream(),
+        }
+    }
+}
+
+fn default_max_in_flight() -> usize {
+    16
+}
+
+fn default_max_per_stream() -> usize {
+    6
+}
+
+/// Result cache TTLs (seconds, 0 disables) and memory budget
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CacheSettings {
+    pub single_value_ttl_secs: u64,
+    pub time_series_ttl_secs: u64,
+    /// Per-aquarium series discovery; the old `probe_metadata_ttl_secs` key is still accepted
+    #[serde(alias = "probe_metadata_ttl_secs")]
+    pub series_metadata_ttl_secs: u64,
+    pub aquarium_ids_ttl_secs: u64,
+    pub max_bytes: usize,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            single_value_ttl_secs: 15,
+            time_series_ttl_secs: 60,
+            series_metadata_ttl_secs: 300,
+            aquarium_ids_ttl_secs: 600,
+            max_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct WidgetsConfig {
     #[serde(default)]
     pub tiles: Vec<TileConfig>,
     #[serde(default)]
     pub charts: Vec<ChartConfig>,
+    /// Named pages, each streaming a subset of the tiles and charts
+    #[serde(default)]
+    pub pages: Vec<PageConfig>,
+    /// Named sets of overrides that several aquariums can share
+    #[serde(default)]
+    pub profiles: BTreeMap<String, WidgetOverrides>,
+    /// Overrides for one aquarium, by aquarium id
+    #[serde(default)]
+    pub aquariums: BTreeMap<String, WidgetOverrides>,
+}
+
+impl WidgetsConfig {
+    /// Fill in InfluxQL for widgets that only have a structured `select`;
+    /// a raw `query` always wins
+    pub fn compile_queries(&mut self) {
+        self.tiles.iter_mut().for_each(TileConfig::compile_query);
+        self.charts.iter_mut().for_each(ChartConfig::compile_queries);
+
+        for overrides in self.profiles.values_mut().chain(self.aquariums.values_mut()) {
+            overrides.tiles.iter_mut().for_each(TileConfig::compile_query);
+            overrides.charts.iter_mut().for_each(ChartConfig::compile_queries);
+            overrides.series.iter_mut().for_each(|s| s.series.compile_query());
+        }
+    }
+
+    /// The widgets for one aquarium: the defaults with its profile's overrides applied,
+    /// then its own. Borrowed as is when the aquarium has no overrides.
+    pub fn for_aquarium(&self, aquarium_id: &str) -> Cow<'_, WidgetsConfig> {
+        let Some(overrides) = self.aquariums.get(aquarium_id) else {
+            return Cow::Borrowed(self);
+        };
+
+        let mut widgets = WidgetsConfig {
+            tiles: self.tiles.clone(),
+            charts: self.charts.clone(),
+            pages: self.pages.clone(),
+            ..WidgetsConfig::default()
+        };
+        if let Some(profile) = overrides.profile.as_ref().and_then(|p| self.profiles.get(p)) {
+            profile.apply(&mut widgets);
+        }
+        overrides.apply(&mut widgets);
+        Cow::Owned(widgets)
+    }
+
+    /// Pages with at least one of their tiles or charts configured
+    pub fn pages(&self) -> impl Iterator<Item = &PageConfig> {
+        self.pages.iter().filter(|p| {
+            self.tiles.iter().any(|t| p.tiles.contains(&t.id))
+                || self.charts.iter().any(|c| p.charts.contains(&c.id))
+        })
+    }
+
+    /// The tiles and charts of one page, in the page's order, or None for an unknown page
+    pub fn page(&self, page_id: &str) -> Option<WidgetsConfig> {
+        let page = self.pages.iter().find(|p| p.id == page_id)?;
+        Some(WidgetsConfig {
+            tiles: page
+                .tiles
+                .iter()
+                .filter_map(|id| self.tiles.iter().find(|t| &t.id == id).cloned())
+                .collect(),
+            charts: page
+                .charts
+                .iter()
+                .filter_map(|id| self.charts.iter().find(|c| &c.id == id).cloned())
+                .collect(),
+            ..WidgetsConfig::default()
+        })
+    }
+}
+
+/// A named page of the dashboard, listing its tiles and charts by id
+#[derive(Debug, Deserialize, Clone)]
+pub struct PageConfig {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub tiles: Vec<String>,
+    #[serde(default)]
+    pub charts: Vec<String>,
+}
+
+/// Changes to the default widgets for an aquarium or a profile. Tiles, charts and chart
+/// series replace the one with the same id or are added; `hide` then removes tiles,
+/// charts, series, series groups, group members, overlays and generated series by id.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct WidgetOverrides {
+    /// Profile applied before these overrides (aquarium overrides only)
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub hide: Vec<String>,
+    #[serde(default)]
+    pub tiles: Vec<TileConfig>,
+    #[serde(default)]
+    pub charts: Vec<ChartConfig>,
+    #[serde(default)]
+    pub series: Vec<ChartSeriesConfig>,
+}
+
+impl WidgetOverrides {
+    fn apply(&self, widgets: &mut WidgetsConfig) {
+        for tile in &self.tiles {
+            upsert(&mut widgets.tiles, tile.clone(), |t| t.id.as_str());
+        }
+        for chart in &self.charts {
+            upsert(&mut widgets.charts, chart.clone(), |c| c.id.as_str());
+        }
+        for entry in &self.series {
+            match widgets.charts.iter_mut().find(|c| c.id == entry.chart) {
+                Some(chart) => upsert(&mut chart.series, entry.series.clone(), |s| s.id.as_str()),
+                None => tracing::warn!("Series {} is for unknown chart {}", entry.series.id, entry.chart),
+            }
+        }
+
+        let shown = |id: &String| !self.hide.contains(id);
+        widgets.tiles.retain(|t| shown(&t.id));
+        widgets.charts.retain(|c| shown(&c.id));
+        for chart in &mut widgets.charts {
+            chart.series.retain(|s| shown(&s.id));
+            chart.series_groups.retain(|g| shown(&g.id));
+            for group in &mut chart.series_groups {
+                group.series.retain(|m| shown(&m.id));
+            }
+            chart.overlays.retain(|o| shown(&o.id));
+            chart.hidden.extend(self.hide.iter().cloned());
+        }
+    }
+}
+
+/// A series added to (or replacing a series of) a chart
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChartSeriesConfig {
+    /// Id of the chart
+    pub chart: String,
+    #[serde(flatten)]
+    pub series: SeriesConfig,
+}
+
+/// Replace the item with the same id as `item`, or append it
+fn upsert<T>(items: &mut Vec<T>, item: T, id: fn(&T) -> &str) {
+    match items.iter().position(|i| id(i) == id(&item)) {
+        Some(idx) => items[idx] = item,
+        None => items.push(item),
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -28,7 +419,27 @@ pub struct TileConfig {
     pub title: String,
     pub unit: String,
     pub precision: i32,
+    /// InfluxQL query (empty when the widget only has Flux or SQL queries)
+    #[serde(default)]
     pub query: String,
+    /// Structured query compiled to InfluxQL when `query` is empty
+    pub select: Option<QuerySpec>,
+    /// Flux query, used when the backend is "flux"
+    pub flux: Option<String>,
+    /// SQL query, used when the backend is "sql"
+    pub sql: Option<String>,
+}
+
+impl TileConfig {
+    fn compile_query(&mut self) {
+        if let Some(spec) = self.select.as_ref().filter(|_| self.query.is_empty()) {
+            self.query = spec.tile_query();
+        }
+    }
+
+    pub fn query_for(&self, backend: Backend) -> Option<&str> {
+        backend.select(&self.query, self.flux.as_deref(), self.sql.as_deref())
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -40,10 +451,164 @@ pub struct ChartConfig {
     pub y_min: Option<f64>,
     pub y_max: Option<f64>,
     pub fraction_digits: Option<i32>,
+    /// How series are reduced to the point budget: "average", "lttb" or "min_max".
+    /// Overlays are on/off states and always use bucket averaging.
+    #[serde(default)]
+    pub downsampling: Downsampling,
     #[serde(default)]
     pub series: Vec<SeriesConfig>,
+    /// Generates further series from the series discovered for each aquarium
+    pub series_template: Option<SeriesTemplate>,
+    /// Grouped queries that each feed several series
+    #[serde(default)]
+    pub series_groups: Vec<SeriesGroupConfig>,
     #[serde(default)]
     pub overlays: Vec<OverlayConfig>,
+    /// Ids hidden by overrides, kept so generated series can be hidden too
+    #[serde(skip)]
+    hidden: Vec<String>,
+}
+
+impl ChartConfig {
+    fn compile_queries(&mut self) {
+        self.series.iter_mut().for_each(SeriesConfig::compile_query);
+        for group in &mut self.series_groups {
+            if let Some(spec) = group.select.as_ref().filter(|_| group.query.is_empty()) {
+                group.query = spec.grouped_series_query(&group.tag);
+            }
+        }
+    }
+
+    /// Configured series followed by the template's expansion over `discovered`, ordered by
+    /// tags. Colors come from the name, so a series keeps its color as others come and go.
+    /// A configured series or group member takes precedence over a discovered series with
+    /// the same name, and a generated id already used in the chart gets a numeric suffix.
+    /// Generated series whose id the aquarium's overrides hide are left out.
+    pub fn series_for(&self, discovered: &HashSet<SeriesMetadata>) -> Vec<SeriesConfig> {
+        let mut series = self.series.clone();
+        let Some(template) = &self.series_template else {
+            return series;
+        };
+
+        let mut matches: Vec<&SeriesMetadata> = discovered
+            .iter()
+            .filter(|s| template.matches(s))
+            .filter(|s| !self.configured_names().any(|name| Some(name) == s.tag("name")))
+            .collect();
+        matches.sort();
+
+        let prefix = self.template_prefix().unwrap_or_default();
+        let taken: HashSet<&str> = self.ids().collect();
+        let mut ids = Vec::new();
+        for discovered in &matches {
+            let name = discovered.tag("name").unwrap_or_default();
+            let base = format!("{}-{}", prefix, slug(name));
+            let mut id = base.clone();
+            let mut suffix = 2;
+            while taken.contains(id.as_str()) || ids.contains(&id) {
+                id = format!("{}-{}", base, suffix);
+                suffix += 1;
+            }
+            if id != base {
+                tracing::warn!(
+                    "Chart {}: generated series id {} for {:?} is already used, using {}",
+                    self.id,
+                    base,
+                    name,
+                    id
+                );
+            }
+            ids.push(id);
+        }
+
+        let generated = matches.into_iter().zip(ids).filter(|(_, id)| !self.hidden.contains(id));
+        series.extend(generated.map(|(discovered, id)| {
+            let name = discovered.tag("name").unwrap_or_default().to_string();
+            let mut select = template.select.clone();
+            select.tags.extend(discovered.tags.clone());
+
+            SeriesConfig {
+                id,
+                color: Some(palette_color(&name).to_string()),
+                query: select.series_query(),
+                select: Some(select),
+                name,
+                flux: None,
+                sql: None,
+            }
+        }));
+        series
+    }
+
+    /// Prefix of the ids the series template generates, if the chart has one
+    pub fn template_prefix(&self) -> Option<&str> {
+        let template = self.series_template.as_ref()?;
+        Some(template.id_prefix.as_deref().unwrap_or(&self.id))
+    }
+
+    /// Ids of the chart and everything configured in it
+    fn ids(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.id.as_str())
+            .chain(self.series.iter().map(|s| s.id.as_str()))
+            .chain(self.series_groups.iter().map(|g| g.id.as_str()))
+            .chain(self.series_groups.iter().flat_map(|g| &g.series).map(|s| s.id.as_str()))
+            .chain(self.overlays.iter().map(|o| o.id.as_str()))
+    }
+
+    /// Names of the explicitly configured series, group members included
+    fn configured_names(&self) -> impl Iterator<Item = &str> {
+        self.series
+            .iter()
+            .map(|s| s.name.as_str())
+            .chain(self.series_groups.iter().flat_map(|g| &g.series).map(|s| s.name.as_str()))
+    }
+}
+
+/// One series per discovered series of `select.measurement` whose tags equal `select.tags`
+/// and whose name matches `name_pattern`. Generated series only have InfluxQL queries.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SeriesTemplate {
+    /// Prefix of generated ids, followed by the name as a slug (defaults to the chart id)
+    pub id_prefix: Option<String>,
+    /// Query for every generated series; the discovered series' tags are added to its `tags`
+    pub select: QuerySpec,
+    /// Regular expression the discovered `name` must match
+    #[serde(default, deserialize_with = "deserialize_pattern")]
+    pub name_pattern: Option<Regex>,
+}
+
+impl SeriesTemplate {
+    fn matches(&self, series: &SeriesMetadata) -> bool {
+        series.measurement == self.select.measurement
+            && self.select.tags.iter().all(|(key, value)| series.tag(key) == Some(value.as_str()))
+            && self.name_pattern.as_ref().is_none_or(|pattern| {
+                series.tag("name").is_some_and(|name| pattern.is_match(name))
+            })
+    }
+}
+
+fn deserialize_pattern<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Regex>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|pattern| Regex::new(&pattern).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Palette color for a generated series, from an FNV-1a hash of its name so it is the
+/// same on every dashboard and across releases
+fn palette_color(name: &str) -> &'static str {
+    let hash = name
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3));
+    SERIES_PALETTE[(hash % SERIES_PALETTE.len() as u64) as usize]
+}
+
+/// Lowercase id fragment: runs of anything but ASCII letters and digits become `-`
+fn slug(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -51,7 +616,67 @@ pub struct SeriesConfig {
     pub id: String,
     pub name: String,
     pub color: Option<String>,
+    /// InfluxQL query (empty when the widget only has Flux or SQL queries)
+    #[serde(default)]
     pub query: String,
+    /// Structured query compiled to InfluxQL when `query` is empty
+    pub select: Option<QuerySpec>,
+    /// Flux query, used when the backend is "flux"
+    pub flux: Option<String>,
+    /// SQL query, used when the backend is "sql"
+    pub sql: Option<String>,
+}
+
+impl SeriesConfig {
+    fn compile_query(&mut self) {
+        if let Some(spec) = self.select.as_ref().filter(|_| self.query.is_empty()) {
+            self.query = spec.series_query();
+        }
+    }
+
+    pub fn query_for(&self, backend: Backend) -> Option<&str> {
+        backend.select(&self.query, self.flux.as_deref(), self.sql.as_deref())
+    }
+}
+
+/// One grouped query (e.g. `GROUP BY "name"`) whose result series are matched to chart
+/// series by the value of `tag`, replacing a round trip per series with one
+#[derive(Debug, Deserialize, Clone)]
+pub struct SeriesGroupConfig {
+    pub id: String,
+    /// Tag the query groups by
+    pub tag: String,
+    /// InfluxQL query (empty when the widget only has Flux or SQL queries)
+    #[serde(default)]
+    pub query: String,
+    /// Structured query compiled to InfluxQL, grouped by `tag`, when `query` is empty
+    pub select: Option<QuerySpec>,
+    /// Flux query, used when the backend is "flux"
+    pub flux: Option<String>,
+    /// SQL query, used when the backend is "sql"
+    pub sql: Option<String>,
+    pub series: Vec<GroupedSeriesConfig>,
+}
+
+impl SeriesGroupConfig {
+    pub fn query_for(&self, backend: Backend) -> Option<&str> {
+        backend.select(&self.query, self.flux.as_deref(), self.sql.as_deref())
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GroupedSeriesConfig {
+    pub id: String,
+    pub name: String,
+    pub color: Option<String>,
+    /// Value of the group's tag for this series (defaults to `name`)
+    pub tag_value: Option<String>,
+}
+
+impl GroupedSeriesConfig {
+    pub fn tag_value(&self) -> &str {
+        self.tag_value.as_deref().unwrap_or(&self.name)
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -59,32 +684,101 @@ pub struct OverlayConfig {
     pub id: String,
     pub name: String,
     pub color: Option<String>,
+    /// InfluxQL query (empty when the widget only has Flux or SQL queries)
+    #[serde(default)]
     pub query: String,
+    /// Flux query, used when the backend is "flux"
+    pub flux: Option<String>,
+    /// SQL query, used when the backend is "sql"
+    pub sql: Option<String>,
 }
 
-pub fn load_influx_config() -> anyhow::Result<InfluxConfig> {
-    let settings = config::Config::builder()
-        .add_source(config::File::with_name("config/influx"))
-        .build()?;
-    
-    Ok(settings.try_deserialize()?)
+impl OverlayConfig {
+    pub fn query_for(&self, backend: Backend) -> Option<&str> {
+        backend.select(&self.query, self.flux.as_deref(), self.sql.as_deref())
+    }
 }
 
-pub fn load_widgets_config() -> anyhow::Result<WidgetsConfig> {
-    let settings = config::Config::builder()
-        .add_source(config::File::with_name("config/widgets"))
-        .build()?;
-    
-    Ok(settings.try_deserialize()?)
+/// Load `influx.toml` from `config_dir`, with `influx` fields in `overrides` taking precedence.
+/// A token source in the overrides replaces the file's, so `token_file` there drops a
+/// `token` from the file and vice versa. The file may be left out when the overrides
+/// supply the host, a token source, the database and the retention policy.
+pub fn load_influx_config(config_dir: &str, overrides: &[(String, String)]) -> anyhow::Result<InfluxConfig> {
+    let overridden = |field: &str| overrides.iter().any(|(f, _)| f == field);
+    let token_overridden = overridden("token") || overridden("token_file");
+    let complete = token_overridden && ["host", "database", "retention_policy"].into_iter().all(overridden);
+
+    let path = format!("{}/influx", config_dir);
+    let mut builder = config::Config::builder()
+        .add_source(config::File::with_name(&path).required(!complete));
+    for (field, value) in overrides {
+        builder = builder.set_override(format!("influx.{}", field), value.as_str())?;
+    }
+
+    let mut config: InfluxConfig = builder
+        .build()?
+        .try_deserialize()
+        .with_context(|| format!("Invalid InfluxDB settings in {}.toml", path))?;
+    if token_overridden && !overridden("token") {
+        config.influx.token.clear();
+    }
+    if token_overridden && !overridden("token_file") {
+        config.influx.token_file = None;
+    }
+    match (config.influx.token.is_empty(), &config.influx.token_file) {
+        (true, None) => anyhow::bail!("No InfluxDB token: set token or token_file"),
+        (false, Some(_)) => anyhow::bail!("Both token and token_file are set for InfluxDB; set only one"),
+        (false, None) => {}
+        (true, Some(token_file)) => {
+            let token = std::fs::read_to_string(token_file)
+                .with_context(|| format!("Failed to read InfluxDB token from {}", token_file))?;
+            config.influx.token = token.trim_end().to_string();
+            anyhow::ensure!(!config.influx.token.is_empty(), "InfluxDB token file {} is empty", token_file);
+        }
+    }
+    Ok(config)
 }
 
-/// Replace template variables in a query string
+/// Load, compile and check a widget configuration file. Warnings are logged;
+/// any error fails the load with every error listed.
+pub fn load_widgets_config(path: &str) -> anyhow::Result<WidgetsConfig> {
+    let (widgets, problems) = check_widgets_file(path)?;
+
+    for warning in problems.iter().filter(|p| p.severity == Severity::Warning) {
+        tracing::warn!("{}", warning.render(path));
+    }
+    let errors: Vec<String> = problems
+        .iter()
+        .filter(|p| p.severity == Severity::Error)
+        .map(|p| p.render(path))
+        .collect();
+    anyhow::ensure!(errors.is_empty(), "{}", errors.join("\n"));
+
+    Ok(widgets)
+}
+
+/// Replace template variables in a query string. Substitution is a single pass,
+/// so values are never themselves expanded; unknown variables are left as is.
 pub fn prepare_query(query: &str, vars: &HashMap<String, String>) -> String {
-    let mut result = query.to_string();
-    for (key, value) in vars {
-        let placeholder = format!("${{{}}}", key);
-        result = result.replace(&placeholder, value);
+    let mut result = String::with_capacity(query.len());
+    let mut rest = query;
+
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find('}').and_then(|end| Some((vars.get(&after[..end])?, end))) {
+            Some((value, end)) => {
+                result.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push_str("${");
+                rest = after;
+            }
+        }
     }
+
+    result.push_str(rest);
     result
 }
 
@@ -103,5 +797,349 @@ mod tests {
         
         assert_eq!(result, "SELECT * FROM apex_probe WHERE host='reef' AND time >= now() - 12h");
     }
+
+    #[test]
+    fn test_range_vars() {
+        let query = "SELECT value FROM apex_probe WHERE time >= ${start} AND time <= ${end} -- ${range}";
+
+        let relative = Backend::InfluxQL.range_vars(&TimeRange::Last { hours: 6 }, 150);
+        assert_eq!(
+            prepare_query(query, &relative),
+            "SELECT value FROM apex_probe WHERE time >= now() - 6h AND time <= now() -- 6h"
+        );
+
+        let window = TimeRange::Between {
+            start_ms: 1_735_688_400_000,
+            end_ms: 1_735_689_600_000,
+        };
+        let absolute = Backend::InfluxQL.range_vars(&window, 150);
+        assert_eq!(
+            prepare_query(query, &absolute),
+            "SELECT value FROM apex_probe WHERE time >= '2024-12-31T23:40:00.000Z' AND time <= '2025-01-01T00:00:00.000Z' -- 20m"
+        );
+        assert_eq!(absolute["hours"], "1");
+        assert_eq!(relative["interval"], "2m");
+        assert_eq!(absolute["interval"], "10s");
+        assert_eq!(Backend::Sql.range_vars(&window, 150)["interval"], "INTERVAL '10 seconds'");
+
+        let (start, _) = Backend::Flux.time_bounds(&window);
+        assert_eq!(start, "2024-12-31T23:40:00.000Z");
+
+        // Out-of-range bounds clamp instead of falling back to 1970
+        let extreme = TimeRange::Between { start_ms: i64::MIN, end_ms: i64::MAX };
+        let (start, end) = Backend::Flux.time_bounds(&extreme);
+        assert!(start.starts_with('-') && end.starts_with('+'));
+        assert!(Backend::InfluxQL.range_vars(&extreme, 150).contains_key("interval"));
+
+        assert!(relative.keys().all(|name| TEMPLATE_VARIABLES.contains(&name.as_str())));
+    }
+
+    #[test]
+    fn test_escape_string() {
+        let query = "SELECT value FROM apex_probe WHERE host = '${source}'";
+        let escaped = |value: &str| {
+            let vars = HashMap::from([("source".to_string(), Backend::InfluxQL.escape_string(value))]);
+            prepare_query(query, &vars)
+        };
+
+        assert_eq!(
+            escaped("x' OR 1=1 --"),
+            r"SELECT value FROM apex_probe WHERE host = 'x\' OR 1=1 --'"
+        );
+        // A trailing backslash must not escape the closing quote
+        assert_eq!(escaped(r"x\"), r"SELECT value FROM apex_probe WHERE host = 'x\\'");
+        assert_eq!(
+            escaped("x'\nDROP SERIES FROM apex_probe"),
+            r"SELECT value FROM apex_probe WHERE host = 'x\'\nDROP SERIES FROM apex_probe'"
+        );
+
+        assert_eq!(Backend::Flux.escape_string("a\"${b}\\"), r#"a\"\${b}\\"#);
+        assert_eq!(Backend::Sql.escape_string("x' OR '1'='1"), "x'' OR ''1''=''1");
+    }
+
+    #[test]
+    fn test_widgets_config_compiles_structured_queries() {
+        let widgets = load_widgets_config(WIDGETS_CONFIG_PATH).unwrap();
+
+        assert!(widgets.tiles.iter().all(|t| !t.query.is_empty()));
+        assert!(widgets.charts.iter().flat_map(|c| &c.series).all(|s| !s.query.is_empty()));
+        assert!(widgets.charts.iter().flat_map(|c| &c.series_groups).all(|g| !g.query.is_empty()));
+
+        let temp = widgets.tiles.iter().find(|t| t.id == "t-temp").unwrap();
+        assert_eq!(
+            temp.query,
+            "SELECT LAST(\"value\") FROM \"apex_probe\" WHERE \"host\"='${source}' AND \"name\"='Tmp' AND \"probe_type\"='temp' AND time >= now() - 5m"
+        );
+    }
+
+    #[test]
+    fn test_series_template_expands_discovered_series() {
+        let mut chart: ChartConfig = toml::from_str(
+            r#"
+            id = "c-temp"
+            title = "Temperature"
+            kind = "multiLine"
+
+            [[series]]
+            id = "s-temp-base"
+            name = "Tmp"
+            query = "SELECT ..."
+
+            [[series]]
+            id = "s-temp-tmpx3"
+            name = "Tmp reference"
+            query = "SELECT ..."
+
+            [series_template]
+            id_prefix = "s-temp"
+            select = { measurement = "apex_probe", tags = { probe_type = "temp" } }
+            "#,
+        )
+        .unwrap();
+        let discovered: HashSet<SeriesMetadata> = [
+            ("apex_probe", "temp", "Tmp"),
+            ("apex_probe", "temp", "Tmpx3"),
+            ("apex_probe", "temp", "Tmp_x10"),
+            ("apex_probe", "temp", "Tmp-x10"),
+            ("apex_probe", "ph", "pH"),
+            ("apex_cor", "", "ReturnPump"),
+        ]
+        .into_iter()
+        .filter_map(|(measurement, probe_type, name)| {
+            SeriesMetadata::from_tags(measurement, |key| match key {
+                "probe_type" => Some(probe_type),
+                _ => Some(name),
+            })
+        })
+        .collect();
+
+        let series = chart.series_for(&discovered);
+        let ids: Vec<&str> = series.iter().map(|s| s.id.as_str()).collect();
+        // Ids that slug alike, or match a configured id, get a suffix
+        assert_eq!(
+            ids,
+            ["s-temp-base", "s-temp-tmpx3", "s-temp-tmp-x10", "s-temp-tmp-x10-2", "s-temp-tmpx3-2"]
+        );
+        assert_eq!(series[4].name, "Tmpx3");
+        assert!(series[4].query.contains("\"name\"='Tmpx3' AND \"probe_type\"='temp'"));
+        let tmpx3_color = series[4].color.clone().unwrap();
+        assert!(SERIES_PALETTE.contains(&tmpx3_color.as_str()));
+
+        // A series keeps its color when the others change
+        chart.series_template.as_mut().unwrap().name_pattern = Some(Regex::new("^Tmpx").unwrap());
+        let series = chart.series_for(&discovered);
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[2].name, "Tmpx3");
+        assert_eq!(series[2].color.as_deref(), Some(tmpx3_color.as_str()));
+    }
+
+    #[test]
+    fn test_aquarium_overrides_merge_with_defaults() {
+        let mut widgets: WidgetsConfig = toml::from_str(
+            r##"
+            [[tiles]]
+            id = "t-temp"
+            title = "Temperature"
+            unit = "F"
+            precision = 1
+            query = "SELECT LAST(value) FROM apex_probe"
+
+            [[tiles]]
+            id = "t-alk"
+            title = "Alkalinity"
+            unit = "dKH"
+            precision = 2
+            query = "SELECT LAST(value) FROM apex_probe"
+
+            [[charts]]
+            id = "c-temp"
+            title = "Temperature"
+            kind = "multiLine"
+
+              [[charts.series]]
+              id = "s-temp-tmp"
+              name = "Tmp"
+              query = "SELECT value FROM apex_probe"
+
+              [[charts.overlays]]
+              id = "o-temp-heater"
+              name = "Heater"
+              query = "SELECT state FROM apex_output"
+
+            [profiles.nano]
+            hide = ["t-alk"]
+
+            [aquariums.Planet_72]
+            profile = "nano"
+            hide = ["o-temp-heater"]
+
+              [[aquariums.Planet_72.tiles]]
+              id = "t-temp"
+              title = "Water Temperature"
+              unit = "C"
+              precision = 1
+              select = { measurement = "apex_probe", tags = { name = "Tmpx3" } }
+
+              [[aquariums.Planet_72.series]]
+              chart = "c-temp"
+              id = "s-temp-tmpx3"
+              name = "Tmpx3"
+              color = "#ff9500"
+              select = { measurement = "apex_probe", tags = { name = "Tmpx3" } }
+            "##,
+        )
+        .unwrap();
+        widgets.compile_queries();
+
+        let planet = widgets.for_aquarium("Planet_72");
+        let tiles: Vec<(&str, &str)> = planet.tiles.iter().map(|t| (t.id.as_str(), t.title.as_str())).collect();
+        assert_eq!(tiles, [("t-temp", "Water Temperature")]);
+        assert!(planet.tiles[0].query.contains("\"name\"='Tmpx3'"));
+        let series: Vec<&str> = planet.charts[0].series.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(series, ["s-temp-tmp", "s-temp-tmpx3"]);
+        assert!(planet.charts[0].overlays.is_empty());
+
+        // Aquariums without overrides get the defaults as they are
+        assert!(matches!(widgets.for_aquarium("Great_Barrier_"), Cow::Borrowed(_)));
+        assert_eq!(widgets.for_aquarium("Great_Barrier_").tiles.len(), 2);
+
+        // Section names keep their case when loaded from a file
+        let path = std::env::temp_dir().join(format!("widgets-overrides-{}.toml", std::process::id()));
+        let mut source = std::fs::read_to_string(WIDGETS_CONFIG_PATH).unwrap();
+        source.push_str("\n[aquariums.Planet_72]\nhide = [\"t-alk\"]\n");
+        std::fs::write(&path, source).unwrap();
+        let loaded = load_widgets_config(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.unwrap().for_aquarium("Planet_72").tiles.iter().all(|t| t.id != "t-alk"));
+    }
+
+    #[test]
+    fn test_hide_applies_to_generated_series() {
+        let widgets: WidgetsConfig = toml::from_str(
+            r#"
+            [[charts]]
+            id = "c-temp"
+            title = "Temperature"
+            kind = "multiLine"
+
+              [charts.series_template]
+              select = { measurement = "apex_probe", tags = { probe_type = "temp" } }
+
+            [profiles.nano]
+            hide = ["c-temp-tmpx3"]
+
+            [aquariums.Planet_72]
+            profile = "nano"
+            hide = ["c-temp-tmpx7"]
+            "#,
+        )
+        .unwrap();
+        let discovered: HashSet<SeriesMetadata> = ["Tmp", "Tmpx3", "Tmpx7"]
+            .into_iter()
+            .filter_map(|name| {
+                SeriesMetadata::from_tags("apex_probe", |key| match key {
+                    "probe_type" => Some("temp"),
+                    _ => Some(name),
+                })
+            })
+            .collect();
+
+        let planet = widgets.for_aquarium("Planet_72");
+        let names: Vec<String> = planet.charts[0].series_for(&discovered).into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["Tmp"]);
+        assert_eq!(widgets.for_aquarium("Tidal_62_AIO_-").charts[0].series_for(&discovered).len(), 3);
+    }
+
+    #[test]
+    fn test_page_selects_its_widgets_in_order() {
+        let widgets = load_widgets_config(WIDGETS_CONFIG_PATH).unwrap();
+
+        let equipment = widgets.page("equipment").unwrap();
+        let charts: Vec<&str> = equipment.charts.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(charts, ["c-atk", "c-ato-reservoir", "c-return-chamber"]);
+        assert_eq!(equipment.tiles.len(), 4);
+        assert!(widgets.page("missing").is_none());
+
+        // A page whose widgets are all hidden for an aquarium isn't listed for it
+        let mut widgets = widgets;
+        widgets.aquariums.entry("Planet_72".to_string()).or_default().hide.push("c-awc".to_string());
+        let planet = widgets.for_aquarium("Planet_72");
+        let pages: Vec<&str> = planet.pages().map(|p| p.id.as_str()).collect();
+        assert_eq!(pages, ["chemistry", "equipment"]);
+    }
+
+    #[test]
+    fn test_influx_overrides_and_token_file() {
+        let dir = std::env::temp_dir().join(format!("influx-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("influx.toml"),
+            "[influx]\nhost = \"http://influx:8086\"\ndatabase = \"neptune\"\nretention_policy = \"autogen\"\n",
+        )
+        .unwrap();
+        let token_file = dir.join("token");
+        std::fs::write(&token_file, "secret\n").unwrap();
+
+        let overrides = [
+            ("host".to_string(), "http://localhost:8086".to_string()),
+            ("request_timeout_ms".to_string(), "5000".to_string()),
+            ("backend".to_string(), "flux".to_string()),
+            ("token_file".to_string(), token_file.to_str().unwrap().to_string()),
+        ];
+        let config = load_influx_config(dir.to_str().unwrap(), &overrides).unwrap();
+
+        assert_eq!(config.influx.host, "http://localhost:8086");
+        assert_eq!(config.influx.database, "neptune");
+        assert_eq!(config.influx.request_timeout_ms, 5000);
+        assert_eq!(config.influx.backend, Backend::Flux);
+        assert_eq!(config.influx.token, "secret");
+
+        // Exactly one of token and token_file
+        let token = ("token".to_string(), "plain".to_string());
+        let both = load_influx_config(dir.to_str().unwrap(), &[overrides[3].clone(), token.clone()]);
+        let neither = load_influx_config(dir.to_str().unwrap(), &overrides[..3]);
+        assert!(both.unwrap_err().to_string().contains("set only one"));
+        assert!(neither.unwrap_err().to_string().contains("No InfluxDB token"));
+        assert_eq!(load_influx_config(dir.to_str().unwrap(), &[token]).unwrap().influx.token, "plain");
+
+        // A token source from a flag or the environment replaces the one in the file
+        std::fs::write(
+            dir.join("influx.toml"),
+            "[influx]\nhost = \"http://influx:8086\"\ntoken = \"from-file\"\ndatabase = \"neptune\"\nretention_policy = \"autogen\"\n",
+        )
+        .unwrap();
+        let config = load_influx_config(dir.to_str().unwrap(), &overrides[3..]).unwrap();
+        assert_eq!(config.influx.token, "secret");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_influx_config_file_is_optional_only_when_overrides_are_complete() {
+        let dir = std::env::temp_dir().join(format!("influx-config-missing-{}", std::process::id()));
+        let mut overrides = vec![
+            ("host".to_string(), "http://localhost:8086".to_string()),
+            ("database".to_string(), "neptune".to_string()),
+            ("token".to_string(), "plain".to_string()),
+        ];
+
+        let partial = load_influx_config(dir.to_str().unwrap(), &overrides);
+        assert!(partial.unwrap_err().to_string().contains("not found"));
+
+        overrides.push(("retention_policy".to_string(), "autogen".to_string()));
+        let config = load_influx_config(dir.to_str().unwrap(), &overrides).unwrap();
+        assert_eq!(config.influx.host, "http://localhost:8086");
+        assert_eq!(config.influx.retention_policy, "autogen");
+    }
+
+    #[test]
+    fn test_prepare_query_does_not_expand_values() {
+        let vars = HashMap::from([
+            ("source".to_string(), "${hours}".to_string()),
+            ("hours".to_string(), "6".to_string()),
+        ]);
+
+        assert_eq!(prepare_query("${source} ${hours} ${missing}", &vars), "${hours} 6 ${missing}");
+    }
 }
 
@@ -0,0 +1,622 @@
+// Config check - Static checks of a widget configuration, located by line
+use crate::infrastructure::config::{
+    ChartConfig, ChartSeriesConfig, SeriesConfig, TileConfig, WidgetsConfig, TEMPLATE_VARIABLES,
+};
+use crate::infrastructure::influxql_filter;
+use anyhow::Context;
+use std::collections::HashMap;
+
+/// Chart kinds the client can draw
+const CHART_KINDS: [&str; 2] = ["line", "multiLine"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The config can't be used
+    Error,
+    /// The config works, but probably not as intended
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub severity: Severity,
+    /// 1-based line of the widget's `id`, when it can be found in the file
+    pub line: Option<usize>,
+    pub widget: String,
+    pub message: String,
+}
+
+impl Problem {
+    /// `path:line: severity: widget: message`, the way compilers report
+    pub fn render(&self, path: &str) -> String {
+        let location = match self.line {
+            Some(line) => format!("{}:{}", path, line),
+            None => path.to_string(),
+        };
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        format!("{}: {}: {}: {}", location, severity, self.widget, self.message)
+    }
+}
+
+/// One configured widget, as far as the checks are concerned
+struct Widget<'a> {
+    id: &'a str,
+    color: Option<&'a str>,
+    query: &'a str,
+    flux: Option<&'a str>,
+    sql: Option<&'a str>,
+    /// Series group members take their data from the group's query
+    has_own_query: bool,
+}
+
+/// Load a widgets file and check it. The error is for a file that can't be read
+/// or deserialized at all; everything else is reported as problems.
+pub fn check_widgets_file(path: &str) -> anyhow::Result<(WidgetsConfig, Vec<Problem>)> {
+    let source = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let settings = config::Config::builder()
+        .add_source(config::File::with_name(path))
+        .build()?;
+
+    let mut widgets: WidgetsConfig = settings.try_deserialize()?;
+    widgets.compile_queries();
+    let problems = check_widgets(&widgets, &source);
+    Ok((widgets, problems))
+}
+
+/// Check ids, chart kinds, colors, template variables and InfluxQL of every widget, and
+/// what pages and overrides refer to. `source` is the file text, used to find the line
+/// of each widget.
+pub fn check_widgets(widgets: &WidgetsConfig, source: &str) -> Vec<Problem> {
+    let lines = id_lines(source);
+    let mut problems = Vec::new();
+    check_section(&widgets.tiles, &widgets.charts, &[], 0, &lines, &mut problems);
+
+    let sections = [("profiles", &widgets.profiles), ("aquariums", &widgets.aquariums)];
+    for (kind, overrides) in sections {
+        for (name, section) in overrides {
+            let start = section_line(source, kind, name);
+            let label = format!("{}.{}", kind, name);
+            let line = (start > 0).then_some(start);
+            check_section(&section.tiles, &section.charts, &section.series, start, &lines, &mut problems);
+
+            let profile = match &section.profile {
+                Some(_) if kind == "profiles" => {
+                    problems.push(Problem {
+                        severity: Severity::Error,
+                        line,
+                        widget: label.clone(),
+                        message: "a profile can't use another profile".to_string(),
+                    });
+                    None
+                }
+                Some(profile) => {
+                    if !widgets.profiles.contains_key(profile) {
+                        problems.push(Problem {
+                            severity: Severity::Error,
+                            line,
+                            widget: label.clone(),
+                            message: format!("unknown profile {:?}", profile),
+                        });
+                    }
+                    widgets.profiles.get(profile)
+                }
+                None => None,
+            };
+
+            // Widgets the overrides can refer to: the defaults, the profile's and their own
+            let mut charts: Vec<&ChartConfig> = widgets.charts.iter().chain(&section.charts).collect();
+            let mut known = all_widgets(&widgets.tiles, &widgets.charts, &[]);
+            known.extend(all_widgets(&section.tiles, &section.charts, &section.series));
+            if let Some(profile) = profile {
+                charts.extend(&profile.charts);
+                known.extend(all_widgets(&profile.tiles, &profile.charts, &profile.series));
+            }
+
+            for entry in section.series.iter().filter(|e| !charts.iter().any(|c| c.id == e.chart)) {
+                problems.push(Problem {
+                    severity: Severity::Error,
+                    line: locate(&lines, &entry.series.id, start, 0),
+                    widget: entry.series.id.clone(),
+                    message: format!("unknown chart {:?}", entry.chart),
+                });
+            }
+            // Series templates may generate an id, so their prefixes count as known
+            let generated = |id: &str| {
+                charts
+                    .iter()
+                    .filter_map(|c| c.template_prefix())
+                    .any(|prefix| id.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('-')))
+            };
+            for id in section
+                .hide
+                .iter()
+                .filter(|id| !known.iter().any(|w| w.id == id.as_str()) && !generated(id))
+            {
+                problems.push(Problem {
+                    severity: Severity::Warning,
+                    line,
+                    widget: label.clone(),
+                    message: format!("hides {:?}, which is not a configured widget", id),
+                });
+            }
+        }
+    }
+
+    // Overrides can add the tiles and charts a page lists
+    let overrides = || widgets.profiles.values().chain(widgets.aquariums.values());
+    let tiles: Vec<&str> = widgets
+        .tiles
+        .iter()
+        .chain(overrides().flat_map(|o| &o.tiles))
+        .map(|t| t.id.as_str())
+        .collect();
+    let charts: Vec<&str> = widgets
+        .charts
+        .iter()
+        .chain(overrides().flat_map(|o| &o.charts))
+        .map(|c| c.id.as_str())
+        .collect();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for page in &widgets.pages {
+        let occurrence = seen.entry(&page.id).or_default();
+        let line = locate(&lines, &page.id, 0, *occurrence);
+        *occurrence += 1;
+
+        let mut report = |message: String| {
+            problems.push(Problem {
+                severity: Severity::Error,
+                line,
+                widget: page.id.clone(),
+                message,
+            })
+        };
+        if page.id.is_empty() {
+            report("empty page id".to_string());
+        }
+        if *occurrence > 1 {
+            report("duplicate page id".to_string());
+        }
+        for id in page.tiles.iter().filter(|id| !tiles.contains(&id.as_str())) {
+            report(format!("unknown tile {:?}", id));
+        }
+        for id in page.charts.iter().filter(|id| !charts.contains(&id.as_str())) {
+            report(format!("unknown chart {:?}", id));
+        }
+    }
+
+    problems.sort_by_key(|p| p.line);
+    problems
+}
+
+/// Check the widgets of the defaults or of one override section, whose ids are searched
+/// for after line `start`
+fn check_section(
+    tiles: &[TileConfig],
+    charts: &[ChartConfig],
+    chart_series: &[ChartSeriesConfig],
+    start: usize,
+    lines: &HashMap<&str, Vec<usize>>,
+    problems: &mut Vec<Problem>,
+) {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+
+    for widget in all_widgets(tiles, charts, chart_series) {
+        let occurrence = seen.entry(widget.id).or_default();
+        let line = locate(lines, widget.id, start, *occurrence);
+        *occurrence += 1;
+
+        let mut report = |severity: Severity, message: String| {
+            problems.push(Problem {
+                severity,
+                line,
+                widget: widget.id.to_string(),
+                message,
+            })
+        };
+
+        if widget.id.is_empty() {
+            report(Severity::Error, "empty id".to_string());
+        }
+        if *occurrence > 1 {
+            report(Severity::Error, "duplicate id".to_string());
+        }
+        if widget.has_own_query && widget.query.is_empty() && widget.flux.is_none() && widget.sql.is_none() {
+            report(Severity::Error, "no query".to_string());
+        }
+        if let Some(color) = widget.color.filter(|c| !is_hex_color(c)) {
+            report(Severity::Error, format!("color {:?} is not #rgb or #rrggbb", color));
+        }
+
+        let queries = [(widget.query, "InfluxQL"), (widget.sql.unwrap_or_default(), "SQL")];
+        for (query, language) in queries {
+            for name in unknown_variables(query) {
+                report(Severity::Error, format!("unknown template variable ${{{}}} in {}", name, language));
+            }
+        }
+        // Flux interpolates `${...}` in strings itself, so unknown names may be intended
+        for name in unknown_variables(widget.flux.unwrap_or_default()) {
+            report(
+                Severity::Warning,
+                format!("${{{}}} in Flux is not a template variable and is left as is", name),
+            );
+        }
+
+        if widget.query.is_empty() {
+            continue;
+        }
+        if let Err(e) = influxql_filter::check_syntax(widget.query) {
+            report(Severity::Error, format!("InfluxQL syntax error: {}", e));
+        } else if let Err(e) = influxql_filter::parse(widget.query) {
+            report(
+                Severity::Warning,
+                format!("InfluxQL filters can't be analyzed, so the widget skips the availability check: {}", e),
+            );
+        }
+    }
+
+    for chart in charts {
+        if !CHART_KINDS.contains(&chart.kind.as_str()) {
+            problems.push(Problem {
+                severity: Severity::Error,
+                line: locate(lines, &chart.id, start, 0),
+                widget: chart.id.clone(),
+                message: format!("kind {:?} is not one of {}", chart.kind, CHART_KINDS.join(", ")),
+            });
+        }
+        for group in chart.series_groups.iter().filter(|g| g.series.is_empty()) {
+            problems.push(Problem {
+                severity: Severity::Error,
+                line: locate(lines, &group.id, start, 0),
+                widget: group.id.clone(),
+                message: "series group has no series".to_string(),
+            });
+        }
+    }
+}
+
+/// Tiles, charts, series, series groups and their members, overlays, and series added to
+/// charts, in file order
+fn all_widgets<'a>(
+    tiles: &'a [TileConfig],
+    charts: &'a [ChartConfig],
+    chart_series: &'a [ChartSeriesConfig],
+) -> Vec<Widget<'a>> {
+    let mut all: Vec<Widget> = tiles
+        .iter()
+        .map(|t| Widget {
+            id: &t.id,
+            color: None,
+            query: &t.query,
+            flux: t.flux.as_deref(),
+            sql: t.sql.as_deref(),
+            has_own_query: true,
+        })
+        .collect();
+
+    for chart in charts {
+        all.push(Widget {
+            id: &chart.id,
+            color: None,
+            query: "",
+            flux: None,
+            sql: None,
+            has_own_query: false,
+        });
+        all.extend(chart.series.iter().map(series_widget));
+        for group in &chart.series_groups {
+            all.push(Widget {
+                id: &group.id,
+                color: None,
+                query: &group.query,
+                flux: group.flux.as_deref(),
+                sql: group.sql.as_deref(),
+                has_own_query: true,
+            });
+            all.extend(group.series.iter().map(|m| Widget {
+                id: &m.id,
+                color: m.color.as_deref(),
+                query: "",
+                flux: None,
+                sql: None,
+                has_own_query: false,
+            }));
+        }
+        all.extend(chart.overlays.iter().map(|o| Widget {
+            id: &o.id,
+            color: o.color.as_deref(),
+            query: &o.query,
+            flux: o.flux.as_deref(),
+            sql: o.sql.as_deref(),
+            has_own_query: true,
+        }));
+    }
+
+    all.extend(chart_series.iter().map(|e| series_widget(&e.series)));
+    all
+}
+
+fn series_widget(series: &SeriesConfig) -> Widget<'_> {
+    Widget {
+        id: &series.id,
+        color: series.color.as_deref(),
+        query: &series.query,
+        flux: series.flux.as_deref(),
+        sql: series.sql.as_deref(),
+        has_own_query: true,
+    }
+}
+
+/// Line of the `occurrence`th `id = "..."` for `id` after line `start`
+fn locate(lines: &HashMap<&str, Vec<usize>>, id: &str, start: usize, occurrence: usize) -> Option<usize> {
+    lines.get(id)?.iter().filter(|&&line| line > start).nth(occurrence).copied()
+}
+
+/// Line of the first table header of the `[kind.name]` section, or 0 if there is none
+fn section_line(source: &str, kind: &str, name: &str) -> usize {
+    let prefixes = [format!("{}.{}", kind, name), format!("{}.\"{}\"", kind, name)];
+    source
+        .lines()
+        .position(|line| {
+            let line = line.trim();
+            let header = line.trim_start_matches('[');
+            header.len() < line.len()
+                && prefixes.iter().any(|prefix| {
+                    header
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with(['.', ']']))
+                })
+        })
+        .map_or(0, |idx| idx + 1)
+}
+
+/// Lines of every `id = "..."` assignment, by id
+fn id_lines(source: &str) -> HashMap<&str, Vec<usize>> {
+    let mut lines: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (idx, line) in source.lines().enumerate() {
+        let Some(value) = line.trim().strip_prefix("id") else {
+            continue;
+        };
+        let Some(value) = value.trim_start().strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let id = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')));
+        if let Some(id) = id {
+            lines.entry(id).or_default().push(idx + 1);
+        }
+    }
+    lines
+}
+
+/// `${name}` placeholders in a query that `prepare_query` won't replace
+fn unknown_variables(query: &str) -> Vec<&str> {
+    let mut unknown = Vec::new();
+    let mut rest = query;
+    while let Some(start) = rest.find("${") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('}') else {
+            break;
+        };
+        let name = &after[..end];
+        if !TEMPLATE_VARIABLES.contains(&name) {
+            unknown.push(name);
+        }
+        rest = &after[end + 1..];
+    }
+    unknown
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::config::WIDGETS_CONFIG_PATH;
+
+    fn check(source: &str) -> Vec<Problem> {
+        let mut widgets: WidgetsConfig = toml::from_str(source).unwrap();
+        widgets.compile_queries();
+        check_widgets(&widgets, source)
+    }
+
+    #[test]
+    fn test_shipped_config_has_no_problems() {
+        let (_, problems) = check_widgets_file(WIDGETS_CONFIG_PATH).unwrap();
+        assert_eq!(problems, []);
+    }
+
+    #[test]
+    fn test_reports_problems_with_lines() {
+        let problems = check(
+            r##"
+[[tiles]]
+id = "t-temp"
+title = "Temperature"
+unit = "F"
+precision = 1
+query = "SELECT LAST(value) FROM apex_probe WHERE host = '${host}'"
+
+[[charts]]
+id = "c-temp"
+title = "Temperature"
+kind = "multiline"
+
+  [[charts.series]]
+  id = "t-temp"
+  name = "Tmp"
+  color = "#00zz00"
+  flux = 'from(bucket: "b") |> map(fn: (r) => ({r with label: "${r.name}"}))'
+
+  [[charts.series]]
+  id = "s-empty"
+  name = "Empty"
+"##,
+        );
+
+        let rendered: Vec<String> = problems.iter().map(|p| p.render("widgets.toml")).collect();
+        assert_eq!(
+            rendered,
+            [
+                "widgets.toml:3: error: t-temp: unknown template variable ${host} in InfluxQL",
+                "widgets.toml:10: error: c-temp: kind \"multiline\" is not one of line, multiLine",
+                "widgets.toml:15: error: t-temp: duplicate id",
+                "widgets.toml:15: error: t-temp: color \"#00zz00\" is not #rgb or #rrggbb",
+                "widgets.toml:15: warning: t-temp: ${r.name} in Flux is not a template variable and is left as is",
+                "widgets.toml:21: error: s-empty: no query",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_override_problems() {
+        let problems = check(
+            r##"
+[[tiles]]
+id = "t-temp"
+title = "Temperature"
+unit = "F"
+precision = 1
+query = "SELECT LAST(value) FROM apex_probe WHERE host = '${source}'"
+
+[aquariums.Planet_72]
+profile = "nano"
+hide = ["t-tmep"]
+
+  [[aquariums.Planet_72.tiles]]
+  id = "t-temp"
+  title = "Temperature"
+  unit = "C"
+  precision = 1
+  query = "SELECT LAST(value) FROM apex_probe WHERE host = '${source}'"
+
+  [[aquariums.Planet_72.series]]
+  chart = "c-temp"
+  id = "s-temp-tmpx3"
+  name = "Tmpx3"
+  query = "SELECT value FROM apex_probe WHERE host = '${source}'"
+"##,
+        );
+
+        let rendered: Vec<String> = problems.iter().map(|p| p.render("widgets.toml")).collect();
+        assert_eq!(
+            rendered,
+            [
+                "widgets.toml:9: error: aquariums.Planet_72: unknown profile \"nano\"",
+                "widgets.toml:9: warning: aquariums.Planet_72: hides \"t-tmep\", which is not a configured widget",
+                "widgets.toml:22: error: s-temp-tmpx3: unknown chart \"c-temp\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hiding_generated_series_is_not_reported() {
+        let problems = check(
+            r#"
+[[charts]]
+id = "c-temp"
+title = "Temperature"
+kind = "multiLine"
+
+  [charts.series_template]
+  id_prefix = "s-temp"
+  select = { measurement = "apex_probe", tags = { probe_type = "temp" } }
+
+[aquariums.Planet_72]
+hide = ["s-temp-tmpx3", "s-tempx3"]
+"#,
+        );
+
+        let rendered: Vec<String> = problems.iter().map(|p| p.render("widgets.toml")).collect();
+        assert_eq!(
+            rendered,
+            ["widgets.toml:11: warning: aquariums.Planet_72: hides \"s-tempx3\", which is not a configured widget"]
+        );
+    }
+
+    #[test]
+    fn test_reports_unknown_page_widgets() {
+        let problems = check(
+            r#"
+[[tiles]]
+id = "t-temp"
+title = "Temperature"
+unit = "F"
+precision = 1
+query = "SELECT LAST(value) FROM apex_probe WHERE host = '${source}'"
+
+[[pages]]
+id = "chemistry"
+title = "Chemistry"
+tiles = ["t-temp", "t-ph"]
+charts = ["c-temp"]
+"#,
+        );
+
+        let rendered: Vec<String> = problems.iter().map(|p| p.render("widgets.toml")).collect();
+        assert_eq!(
+            rendered,
+            [
+                "widgets.toml:10: error: chemistry: unknown tile \"t-ph\"",
+                "widgets.toml:10: error: chemistry: unknown chart \"c-temp\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unparseable_influxql_is_a_warning() {
+        let problems = check(
+            r#"
+[[tiles]]
+id = "t-temp"
+title = "Temperature"
+unit = "F"
+precision = 1
+query = "SELECT LAST(value) FROM /apex_.*/ WHERE host = '${source}'"
+"#,
+        );
+
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].severity, Severity::Warning);
+        assert_eq!(problems[0].line, Some(3));
+    }
+
+    #[test]
+    fn test_invalid_influxql_is_an_error() {
+        let problems = check(
+            r#"
+[[tiles]]
+id = "t-temp"
+title = "Temperature"
+unit = "F"
+precision = 1
+query = "SELECT LAST(value) FROM apex_probe WHERE host = '${source}"
+
+[[tiles]]
+id = "t-ph"
+title = "pH"
+unit = ""
+precision = 2
+query = "SELECT LAST(value) WHERE host = '${source}'"
+"#,
+        );
+
+        let rendered: Vec<String> = problems.iter().map(|p| p.render("widgets.toml")).collect();
+        assert_eq!(
+            rendered,
+            [
+                "widgets.toml:3: error: t-temp: InfluxQL syntax error: unterminated literal starting with '",
+                "widgets.toml:10: error: t-ph: InfluxQL syntax error: no FROM clause",
+            ]
+        );
+    }
+}
@@ -0,0 +1,111 @@
+// Config reload - Swaps in a new widgets.toml on file change or SIGHUP
+use crate::infrastructure::config::{load_widgets_config, WidgetsConfig};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+
+/// How often the widgets file's modification time is checked
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Atomically swappable config. Readers take a snapshot that stays valid
+/// (and unchanged) for as long as they hold it.
+pub struct ConfigHandle<T> {
+    current: RwLock<Arc<T>>,
+}
+
+impl<T> ConfigHandle<T> {
+    pub fn new(config: T) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    pub fn load(&self) -> Arc<T> {
+        self.current.read().expect("config handle poisoned").clone()
+    }
+
+    pub fn store(&self, config: T) {
+        *self.current.write().expect("config handle poisoned") = Arc::new(config);
+    }
+}
+
+/// Reload `path` into `handle` whenever the file changes or the process gets SIGHUP.
+/// A config that fails to load or validate is logged and the current one is kept.
+pub fn spawn_widgets_reload(handle: Arc<ConfigHandle<WidgetsConfig>>, path: String) -> anyhow::Result<JoinHandle<()>> {
+    let mut hangups = signal(SignalKind::hangup())?;
+    let mut modified = modified_time(&path);
+
+    Ok(tokio::spawn(async move {
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = poll.tick() => {
+                    let current = modified_time(&path);
+                    if current == modified {
+                        continue;
+                    }
+                    modified = current;
+                    tracing::info!("{} changed, reloading widgets config", path);
+                }
+                _ = hangups.recv() => {
+                    modified = modified_time(&path);
+                    tracing::info!("SIGHUP received, reloading widgets config from {}", path);
+                }
+            }
+
+            reload(&handle, &path);
+        }
+    }))
+}
+
+fn reload(handle: &ConfigHandle<WidgetsConfig>, path: &str) {
+    match load_widgets_config(path) {
+        Ok(widgets) => {
+            handle.store(widgets);
+            tracing::info!("Reloaded widgets config from {}", path);
+        }
+        Err(e) => {
+            tracing::error!("Keeping the current widgets config; {} is invalid: {:#}", path, e);
+        }
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILE: &str = r#"
+        [[tiles]]
+        id = "t-temp"
+        title = "Temperature"
+        unit = "F"
+        precision = 1
+        select = { measurement = "apex_probe" }
+    "#;
+
+    #[test]
+    fn test_invalid_edit_keeps_current_config() {
+        let path = std::env::temp_dir().join(format!("widgets-reload-{}.toml", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, TILE).unwrap();
+        let handle = ConfigHandle::new(load_widgets_config(path).unwrap());
+        let snapshot = handle.load();
+
+        std::fs::write(path, format!("{}{}", TILE, TILE)).unwrap();
+        reload(&handle, path);
+        assert!(Arc::ptr_eq(&snapshot, &handle.load()));
+
+        std::fs::write(path, TILE.replace("t-temp", "t-water")).unwrap();
+        reload(&handle, path);
+        assert_eq!(handle.load().tiles[0].id, "t-water");
+        // Readers holding the old snapshot are unaffected
+        assert_eq!(snapshot.tiles[0].id, "t-temp");
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+// Fixture repository - Answers telemetry queries from a dataset on disk (offline dev and demos)
+use crate::application::telemetry_repository::{SeriesMetadata, TelemetryRepository};
+use crate::domain::downsampling::Downsampling;
+use crate::domain::telemetry::{TaggedSeries, TimeSeriesPoint};
+use crate::domain::time_range::TimeRange;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Dataset file layout (TOML or JSON)
+#[derive(Debug, Deserialize, Clone)]
+pub struct FixtureDataset {
+    #[serde(default)]
+    pub series: Vec<FixtureSeries>,
+}
+
+/// One recorded series. Values are evenly spaced and end at the time of the
+/// query, so the data always looks live.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FixtureSeries {
+    pub measurement: String,
+    /// Field name; when set, only queries mentioning it match this series
+    pub field: Option<String>,
+    pub tags: HashMap<String, String>,
+    pub interval_secs: i64,
+    pub values: Vec<f64>,
+}
+
+impl FixtureSeries {
+    fn points(&self, now_ms: i64) -> Vec<TimeSeriesPoint> {
+        let interval_ms = self.interval_secs * 1000;
+        let last = self.values.len() as i64 - 1;
+        self.values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| TimeSeriesPoint::new(now_ms - (last - i as i64) * interval_ms, *value))
+            .collect()
+    }
+
+    fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FixtureRepository {
+    dataset: FixtureDataset,
+}
+
+impl FixtureRepository {
+    pub fn new(dataset: FixtureDataset) -> Self {
+        Self { dataset }
+    }
+
+    /// Load a dataset file; the format is picked from the extension
+    pub fn load(path: &str) -> Result<Self> {
+        let settings = config::Config::builder()
+            .add_source(config::File::with_name(path))
+            .build()
+            .with_context(|| format!("Failed to read fixture dataset {}", path))?;
+
+        Ok(Self::new(settings.try_deserialize()?))
+    }
+
+    /// Series whose measurement, tags and field match the query
+    fn matching_series(&self, query: &str) -> Vec<&FixtureSeries> {
+        let Some(measurement) = measurement(query) else {
+            return Vec::new();
+        };
+        let filters = tag_filters(query);
+
+        self.dataset
+            .series
+            .iter()
+            .filter(|s| {
+                s.measurement == measurement
+                    && filters.iter().all(|(k, v)| s.tag(k) == Some(v.as_str()))
+                    && s.field.as_ref().is_none_or(|f| query.contains(f.as_str()))
+            })
+            .collect()
+    }
+
+    /// First series matching the query
+    fn find_series(&self, query: &str) -> Option<&FixtureSeries> {
+        self.matching_series(query).into_iter().next()
+    }
+}
+
+/// The series' points within the query's lookback, downsampled
+fn windowed_points(
+    series: &FixtureSeries,
+    query: &str,
+    max_points: usize,
+    downsampling: Downsampling,
+) -> Vec<TimeSeriesPoint> {
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    let mut points = series.points(now_ms);
+    if let Some(lookback) = lookback_ms(query) {
+        points.retain(|p| p.time_ms >= now_ms - lookback);
+    }
+
+    downsampling.apply(points, max_points)
+}
+
+/// Measurement name following `FROM`, with quotes stripped
+fn measurement(query: &str) -> Option<String> {
+    let upper = query.to_ascii_uppercase();
+    let start = upper.find(" FROM ")? + " FROM ".len();
+    let name = query[start..].split_whitespace().next()?;
+    Some(name.trim_matches('"').to_string())
+}
+
+/// `"tag"='value'` predicates in the query (only what the widget queries use)
+fn tag_filters(query: &str) -> Vec<(String, String)> {
+    let mut filters = Vec::new();
+    let mut rest = query;
+
+    while let Some(eq) = rest.find("='") {
+        let key = rest[..eq]
+            .trim_end()
+            .rsplit(|c: char| c.is_whitespace() || c == '(')
+            .next()
+            .unwrap_or_default()
+            .trim_matches('"');
+        let after = &rest[eq + 2..];
+        let Some(end) = after.find('\'') else {
+            break;
+        };
+        filters.push((key.to_string(), after[..end].to_string()));
+        rest = &after[end + 1..];
+    }
+
+    filters
+}
+
+/// Tags after `GROUP BY`, besides the `time(...)` bucket, with quotes stripped
+fn group_by_tags(query: &str) -> Vec<String> {
+    let upper = query.to_ascii_uppercase();
+    let Some(start) = upper.find("GROUP BY ") else {
+        return Vec::new();
+    };
+    query[start + "GROUP BY ".len()..]
+        .split(',')
+        .filter_map(|part| part.split_whitespace().next())
+        .filter(|part| !part.to_ascii_lowercase().starts_with("time("))
+        .map(|tag| tag.trim_matches('"').to_string())
+        .collect()
+}
+
+/// Window start from `now() - <n><unit>`, in milliseconds before now
+fn lookback_ms(query: &str) -> Option<i64> {
+    let start = query.find("now() - ")? + "now() - ".len();
+    let spec: String = query[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect();
+    let (digits, unit) = spec.split_at(spec.find(|c: char| !c.is_ascii_digit())?);
+    let amount: i64 = digits.parse().ok()?;
+    let unit_ms = match unit {
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        "w" => 604_800_000,
+        _ => return None,
+    };
+    Some(amount * unit_ms)
+}
+
+#[async_trait]
+impl TelemetryRepository for FixtureRepository {
+    async fn list_aquarium_ids(&self) -> Result<Vec<String>> {
+        let hosts: BTreeSet<&str> = self
+            .dataset
+            .series
+            .iter()
+            .filter(|s| s.measurement == "apex_probe")
+            .filter_map(|s| s.tag("host"))
+            .collect();
+
+        Ok(hosts.into_iter().map(str::to_string).collect())
+    }
+
+    async fn get_series_metadata(&self, aquarium_id: &str, _range: &TimeRange) -> Result<Vec<SeriesMetadata>> {
+        // Fixture series always end "now", so every series has data in any window
+        let series: BTreeSet<SeriesMetadata> = self
+            .dataset
+            .series
+            .iter()
+            .filter(|s| s.tag("host") == Some(aquarium_id))
+            .filter_map(|s| SeriesMetadata::from_tags(&s.measurement, |key| s.tag(key)))
+            .collect();
+
+        Ok(series.into_iter().collect())
+    }
+
+    async fn query_single_value(&self, query: &str) -> Result<Option<f64>> {
+        Ok(self
+            .find_series(query)
+            .and_then(|s| s.values.last().copied()))
+    }
+
+    async fn query_time_series_downsampled(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> Result<Vec<TimeSeriesPoint>> {
+        Ok(self
+            .find_series(query)
+            .map(|series| windowed_points(series, query, max_points, downsampling))
+            .unwrap_or_default())
+    }
+
+    async fn query_tagged_series(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> Result<Vec<TaggedSeries>> {
+        // One series per matching fixture, tagged with its values of the GROUP BY tags
+        let group_by = group_by_tags(query);
+        Ok(self
+            .matching_series(query)
+            .into_iter()
+            .map(|series| {
+                let tags: BTreeMap<String, String> = group_by
+                    .iter()
+                    .filter_map(|tag| Some((tag.clone(), series.tag(tag)?.to_string())))
+                    .collect();
+                TaggedSeries::new(tags, windowed_points(series, query, max_points, downsampling))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATASET: &str = r#"
+        [[series]]
+        measurement = "apex_probe"
+        interval_secs = 3600
+        values = [78.0, 78.2, 78.4]
+        tags = { host = "Planet_72", probe_type = "temp", name = "Tmp" }
+
+        [[series]]
+        measurement = "apex_cor"
+        field = "rpm"
+        interval_secs = 60
+        values = [1800.0]
+        tags = { host = "Planet_72", name = "ReturnPump" }
+    "#;
+
+    fn repository() -> FixtureRepository {
+        FixtureRepository::new(toml::from_str(DATASET).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_answers_widget_queries() {
+        let repo = repository();
+
+        let tile = "SELECT LAST(value) FROM \"apex_probe\" WHERE \"host\"='Planet_72' AND \"probe_type\"='temp' AND \"name\"='Tmp' AND time >= now() - 5m";
+        assert_eq!(repo.query_single_value(tile).await.unwrap(), Some(78.4));
+
+        let series = "SELECT LAST(value) AS value FROM \"apex_probe\" WHERE \"host\"='Planet_72' AND \"probe_type\"='temp' AND \"name\"='Tmp' AND time >= now() - 1h GROUP BY time(1m)";
+        let points = repo.query_time_series_downsampled(series, 150, Downsampling::Average).await.unwrap();
+        assert_eq!(points.len(), 2);
+
+        let rpm = "SELECT LAST(rpm) FROM \"apex_cor\" WHERE \"host\"='Planet_72' AND \"name\"='ReturnPump'";
+        assert_eq!(repo.query_single_value(rpm).await.unwrap(), Some(1800.0));
+        let load = "SELECT LAST(load_pct) FROM \"apex_cor\" WHERE \"host\"='Planet_72' AND \"name\"='ReturnPump'";
+        assert_eq!(repo.query_single_value(load).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_answers_grouped_queries() {
+        let mut repo = repository();
+        repo.dataset.series.push(FixtureSeries {
+            measurement: "apex_probe".to_string(),
+            field: None,
+            tags: [("host", "Planet_72"), ("probe_type", "temp"), ("name", "Tmpx3")]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            interval_secs: 60,
+            values: vec![77.1, 77.3],
+        });
+
+        let query = "SELECT LAST(\"value\") AS value FROM \"apex_probe\" WHERE \"host\"='Planet_72' AND \"probe_type\"='temp' AND time >= now() - 6h AND time <= now() GROUP BY time(2m), \"name\" fill(none)";
+        let series = repo.query_tagged_series(query, 150, Downsampling::Average).await.unwrap();
+
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].tag("name"), Some("Tmp"));
+        assert_eq!(series[0].tags.len(), 1);
+        assert_eq!(series[0].points.len(), 3);
+        assert_eq!(series[1].tag("name"), Some("Tmpx3"));
+        assert_eq!(series[1].points.last().unwrap().value, 77.3);
+    }
+
+    #[tokio::test]
+    async fn test_demo_dataset_loads() {
+        let repo = FixtureRepository::load("fixtures/demo.toml").unwrap();
+        assert_eq!(
+            repo.list_aquarium_ids().await.unwrap(),
+            vec!["Great_Barrier_", "Planet_72"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discovers_aquariums_and_series() {
+        let repo = repository();
+
+        assert_eq!(repo.list_aquarium_ids().await.unwrap(), vec!["Planet_72"]);
+        let series = repo.get_series_metadata("Planet_72", &TimeRange::Last { hours: 6 }).await.unwrap();
+        let tags = |pairs: &[(&str, &str)]| {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        assert_eq!(
+            series,
+            vec![
+                SeriesMetadata {
+                    measurement: "apex_cor".to_string(),
+                    tags: tags(&[("name", "ReturnPump")]),
+                },
+                SeriesMetadata {
+                    measurement: "apex_probe".to_string(),
+                    tags: tags(&[("name", "Tmp"), ("probe_type", "temp")]),
+                },
+            ]
+        );
+        assert!(repo.get_series_metadata("Great_Barrier_", &TimeRange::Last { hours: 6 }).await.unwrap().is_empty());
+    }
+}
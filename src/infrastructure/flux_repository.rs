@@ -0,0 +1,305 @@
+// InfluxDB v2 Flux repository implementation
+use crate::application::telemetry_repository::{
+    SeriesMetadata, TelemetryRepository, DISCOVERED_MEASUREMENTS,
+};
+use crate::domain::downsampling::Downsampling;
+use crate::domain::telemetry::{TaggedSeries, TimeSeriesPoint};
+use crate::domain::time_range::TimeRange;
+use crate::infrastructure::config::{Backend, InfluxSettings};
+use crate::infrastructure::influx_http::InfluxHttpClient;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// One data row of an annotated CSV response, keyed by column name
+type FluxRecord = HashMap<String, String>;
+
+#[derive(Debug, Clone)]
+pub struct FluxRepository {
+    host: String,
+    org: Option<String>,
+    bucket: String,
+    http: InfluxHttpClient,
+}
+
+impl FluxRepository {
+    pub fn new(settings: &InfluxSettings) -> Result<Self> {
+        Ok(Self {
+            host: settings.host.trim_end_matches('/').to_string(),
+            org: settings.org.clone(),
+            bucket: settings.bucket(),
+            http: InfluxHttpClient::new(settings)?,
+        })
+    }
+
+    fn build_query_url(&self) -> String {
+        match &self.org {
+            Some(org) => format!("{}/api/v2/query?org={}", self.host, urlencoding::encode(org)),
+            None => format!("{}/api/v2/query", self.host),
+        }
+    }
+
+    async fn execute_query(&self, flux: &str) -> Result<Vec<FluxRecord>> {
+        let url = self.build_query_url();
+        let response = self
+            .http
+            .send(|client| {
+                client
+                    .post(&url)
+                    .header("Content-Type", "application/vnd.flux")
+                    .header("Accept", "application/csv")
+                    .body(flux.to_string())
+            })
+            .await?;
+
+        let body = response
+            .text()
+            .await
+            .context("Failed to read InfluxDB response")?;
+        parse_annotated_csv(&body)
+    }
+}
+
+/// Parse an annotated CSV response into records. Annotation rows and blank
+/// lines start a new table, whose first plain row is its header.
+fn parse_annotated_csv(body: &str) -> Result<Vec<FluxRecord>> {
+    let mut records: Vec<FluxRecord> = Vec::new();
+    let mut header: Option<Vec<String>> = None;
+
+    for line in body.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with('#') {
+            header = None;
+            continue;
+        }
+
+        let fields = split_csv_line(line);
+        match &header {
+            None => header = Some(fields),
+            Some(columns) => records.push(columns.iter().cloned().zip(fields).collect()),
+        }
+    }
+
+    // Errors during execution come back in-band as an "error" table
+    if let Some(error) = records
+        .iter()
+        .find_map(|r| r.get("error").filter(|e| !e.is_empty()))
+    {
+        anyhow::bail!("InfluxDB Flux error: {}", error);
+    }
+
+    Ok(records)
+}
+
+/// Split one CSV line, honouring double-quoted fields and `""` escapes
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Quote a value as a Flux string literal
+fn flux_string(value: &str) -> String {
+    format!("\"{}\"", Backend::Flux.escape_string(value))
+}
+
+fn record_value(record: &FluxRecord) -> Option<f64> {
+    record.get("_value")?.parse().ok()
+}
+
+fn record_point(record: &FluxRecord) -> Option<TimeSeriesPoint> {
+    let time = chrono::DateTime::parse_from_rfc3339(record.get("_time")?).ok()?;
+    Some(TimeSeriesPoint::new(time.timestamp_millis(), record_value(record)?))
+}
+
+/// Tag columns of a record: everything but Flux's own `_` columns and the result/table ids
+fn record_tags(record: &FluxRecord) -> BTreeMap<String, String> {
+    record
+        .iter()
+        .filter(|(column, value)| {
+            !column.is_empty()
+                && !column.starts_with('_')
+                && !matches!(column.as_str(), "result" | "table")
+                && !value.is_empty()
+        })
+        .map(|(column, value)| (column.clone(), value.clone()))
+        .collect()
+}
+
+#[async_trait]
+impl TelemetryRepository for FluxRepository {
+    async fn list_aquarium_ids(&self) -> Result<Vec<String>> {
+        let flux = format!(
+            "import \"influxdata/influxdb/schema\"\n\
+             schema.tagValues(bucket: {}, tag: \"host\", predicate: (r) => r._measurement == \"apex_probe\", start: -30d)",
+            flux_string(&self.bucket)
+        );
+        let records = self.execute_query(&flux).await?;
+
+        Ok(records
+            .into_iter()
+            .filter_map(|mut r| r.remove("_value"))
+            .collect())
+    }
+
+    async fn get_series_metadata(&self, aquarium_id: &str, range: &TimeRange) -> Result<Vec<SeriesMetadata>> {
+        // One row per measurement + identifying tags that has data for this host in the window.
+        // Missing tag columns (e.g. probe_type on apex_cor) group as null and come back empty.
+        let (start, stop) = Backend::Flux.time_bounds(range);
+        let measurements: Vec<String> = DISCOVERED_MEASUREMENTS
+            .iter()
+            .map(|(measurement, _)| format!("r._measurement == {}", flux_string(measurement)))
+            .collect();
+        let columns: BTreeSet<&str> = DISCOVERED_MEASUREMENTS
+            .iter()
+            .flat_map(|(_, tags)| tags.iter().copied())
+            .chain(["_measurement"])
+            .collect();
+        let columns = columns.iter().map(|c| flux_string(c)).collect::<Vec<_>>().join(", ");
+        let flux = format!(
+            "from(bucket: {})\n\
+             |> range(start: {}, stop: {})\n\
+             |> filter(fn: (r) => ({}) and r.host == {})\n\
+             |> group(columns: [{}])\n\
+             |> first()\n\
+             |> keep(columns: [{}])",
+            flux_string(&self.bucket),
+            start,
+            stop,
+            measurements.join(" or "),
+            flux_string(aquarium_id),
+            columns,
+            columns
+        );
+
+        tracing::debug!("Executing series metadata query: {}", flux);
+        let records = self.execute_query(&flux).await?;
+
+        let series: BTreeSet<SeriesMetadata> = records
+            .iter()
+            .filter_map(|r| {
+                SeriesMetadata::from_tags(r.get("_measurement")?, |key| r.get(key).map(String::as_str))
+            })
+            .collect();
+
+        Ok(series.into_iter().collect())
+    }
+
+    async fn query_single_value(&self, query: &str) -> Result<Option<f64>> {
+        let records = self.execute_query(query).await?;
+        Ok(records.iter().rev().find_map(record_value))
+    }
+
+    async fn query_time_series_downsampled(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> Result<Vec<TimeSeriesPoint>> {
+        let records = self.execute_query(query).await?;
+
+        let mut points: Vec<TimeSeriesPoint> = records.iter().filter_map(record_point).collect();
+        points.sort_by_key(|p| p.time_ms);
+
+        Ok(downsampling.apply(points, max_points))
+    }
+
+    async fn query_tagged_series(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> Result<Vec<TaggedSeries>> {
+        let records = self.execute_query(query).await?;
+
+        let mut grouped: BTreeMap<BTreeMap<String, String>, Vec<TimeSeriesPoint>> = BTreeMap::new();
+        for record in &records {
+            if let Some(point) = record_point(record) {
+                grouped.entry(record_tags(record)).or_default().push(point);
+            }
+        }
+
+        Ok(grouped
+            .into_iter()
+            .map(|(tags, mut points)| {
+                points.sort_by_key(|p| p.time_ms);
+                TaggedSeries::new(tags, downsampling.apply(points, max_points))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_annotated_csv_tables() {
+        let body = "#group,false,false,true,false,false\r\n\
+                    #datatype,string,long,string,dateTime:RFC3339,double\r\n\
+                    #default,_result,,,,\r\n\
+                    ,result,table,name,_time,_value\r\n\
+                    ,,0,Tmp,2025-01-01T00:00:00Z,78.1\r\n\
+                    ,,0,Tmp,2025-01-01T00:01:00Z,78.2\r\n\
+                    \r\n\
+                    #datatype,string,long,string,string\r\n\
+                    ,result,table,probe_type,note\r\n\
+                    ,,1,ph,\"calibrated, \"\"twice\"\"\"\r\n";
+
+        let records = parse_annotated_csv(body).unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1]["_value"], "78.2");
+        assert_eq!(records[2]["probe_type"], "ph");
+        assert_eq!(records[2]["note"], "calibrated, \"twice\"");
+    }
+
+    #[test]
+    fn test_record_tags_skip_flux_columns() {
+        let body = ",result,table,_start,_time,_value,_field,_measurement,host,name\n\
+                    ,_result,0,2025-01-01T00:00:00Z,2025-01-01T00:01:00Z,8.1,value,apex_probe,Planet_72,pH\n";
+
+        let records = parse_annotated_csv(body).unwrap();
+
+        assert_eq!(
+            record_tags(&records[0]).into_iter().collect::<Vec<_>>(),
+            [
+                ("host".to_string(), "Planet_72".to_string()),
+                ("name".to_string(), "pH".to_string()),
+            ]
+        );
+        assert_eq!(record_point(&records[0]).unwrap().value, 8.1);
+    }
+
+    #[test]
+    fn test_parse_annotated_csv_error_table() {
+        let body = "#datatype,string,string\n\
+                    #group,true,true\n\
+                    #default,,\n\
+                    ,error,reference\n\
+                    ,\"type error: missing argument\",897\n";
+
+        let error = parse_annotated_csv(body).unwrap_err();
+        assert!(error.to_string().contains("missing argument"));
+    }
+
+    #[test]
+    fn test_flux_string_escapes() {
+        assert_eq!(flux_string(r#"a"b\c"#), r#""a\"b\\c""#);
+    }
+}
@@ -0,0 +1,222 @@
+// Shared InfluxDB HTTP client - connection pooling, timeouts and retries
+use crate::infrastructure::config::InfluxSettings;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Upper bound for a single retry delay
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Longest `Retry-After` honored; a server asking for more fails the query instead
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct InfluxHttpClient {
+    /// Shared client so connections and TLS sessions are pooled across queries
+    client: reqwest::Client,
+    token: String,
+    max_retries: u32,
+    retry_backoff: Duration,
+}
+
+impl InfluxHttpClient {
+    pub fn new(settings: &InfluxSettings) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_millis(settings.connect_timeout_ms))
+            .timeout(Duration::from_millis(settings.request_timeout_ms))
+            .gzip(true)
+            .build()
+            .context("Failed to build InfluxDB HTTP client")?;
+
+        Ok(Self {
+            client,
+            token: settings.token.clone(),
+            max_retries: settings.max_retries,
+            retry_backoff: Duration::from_millis(settings.retry_backoff_ms),
+        })
+    }
+
+    /// Send the request built by `build` with the auth token, retrying transient failures.
+    /// Returns the first successful response; other statuses become errors with the body.
+    pub async fn send<F>(&self, build: F) -> Result<reqwest::Response>
+    where
+        F: Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+    {
+        let mut attempt = 0;
+        loop {
+            let result = build(&self.client)
+                .header("Authorization", format!("Token {}", self.token))
+                .send()
+                .await;
+
+            let backoff = retry_delay(self.retry_backoff, attempt);
+            let delay = match result {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response)
+                    if is_retryable_status(response.status())
+                        && attempt < self.max_retries
+                        && let Some(delay) = status_retry_delay(&response, backoff) =>
+                {
+                    tracing::warn!(
+                        "InfluxDB returned {} (attempt {}/{}), retrying in {:?}",
+                        response.status(),
+                        attempt + 1,
+                        self.max_retries + 1,
+                        delay
+                    );
+                    delay
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let body = response.text().await.unwrap_or_default();
+                    anyhow::bail!("InfluxDB query failed with status {}: {}", status, body);
+                }
+                Err(e) if is_retryable_error(&e) && attempt < self.max_retries => {
+                    tracing::warn!(
+                        "InfluxDB request failed (attempt {}/{}), retrying: {}",
+                        attempt + 1,
+                        self.max_retries + 1,
+                        e
+                    );
+                    backoff
+                }
+                Err(e) => return Err(e).context("Failed to send request to InfluxDB"),
+            };
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// 429 and 5xx responses are worth retrying; other errors will not go away
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Connection failures (refused, reset, connect timeout) are transient; builder, URL and
+/// other request errors would fail the same way again. A query that hit the request
+/// timeout is not retried: another full timeout would outlast the dashboard's widget
+/// deadline while still holding its scheduler permit.
+fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_connect()
+}
+
+/// Delay before retrying a retryable status: the server's `Retry-After` if it sent one,
+/// else `backoff`. None when `Retry-After` is longer than `MAX_RETRY_AFTER`.
+fn status_retry_delay(response: &reqwest::Response, backoff: Duration) -> Option<Duration> {
+    let Some(value) = response.headers().get(RETRY_AFTER) else {
+        return Some(backoff);
+    };
+    match value.to_str().ok().and_then(|value| parse_retry_after(value, Utc::now())) {
+        Some(delay) if delay > MAX_RETRY_AFTER => None,
+        Some(delay) => Some(delay),
+        None => Some(backoff),
+    }
+}
+
+/// `Retry-After` as delay-seconds or an HTTP date; a date in the past means no delay
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&Utc) - now).to_std().unwrap_or_default())
+}
+
+/// Exponential backoff with jitter: a random delay in [backoff/2, backoff] for this attempt
+fn retry_delay(base: Duration, attempt: u32) -> Duration {
+    let backoff = base
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_RETRY_BACKOFF);
+    let half = backoff / 2;
+    let jitter_ms = match half.as_millis() as u64 {
+        0 => 0,
+        range => RandomState::new().build_hasher().finish() % (range + 1),
+    };
+    half + Duration::from_millis(jitter_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::config::Backend;
+    use axum::{routing::get, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_request_timeout_is_not_retried() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route(
+            "/query",
+            get(move || async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                ""
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = InfluxHttpClient::new(&InfluxSettings {
+            host: format!("http://{}", addr),
+            token: "token".to_string(),
+            token_file: None,
+            database: "neptune".to_string(),
+            retention_policy: "autogen".to_string(),
+            backend: Backend::InfluxQL,
+            org: None,
+            bucket: None,
+            connect_timeout_ms: 1_000,
+            request_timeout_ms: 100,
+            max_retries: 2,
+            retry_backoff_ms: 0,
+        })
+        .unwrap();
+
+        let url = format!("http://{}/query", addr);
+        assert!(client.send(|http| http.get(&url)).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_retry_delay_is_jittered_and_capped() {
+        let base = Duration::from_millis(200);
+        for attempt in 0..3 {
+            let delay = retry_delay(base, attempt);
+            let backoff = base * 2u32.pow(attempt);
+            assert!(delay >= backoff / 2 && delay <= backoff);
+        }
+        assert!(retry_delay(base, 20) <= MAX_RETRY_BACKOFF);
+    }
+
+    #[test]
+    fn test_retryable_status() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+        assert!(!is_retryable_status(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2026 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(parse_retry_after("3", now), Some(Duration::from_secs(3)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2026 07:28:10 GMT", now),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2026 07:27:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
@@ -1,16 +1,23 @@
 // InfluxDB repository implementation
-use crate::application::telemetry_repository::{ProbeMetadata, TelemetryRepository};
-use crate::domain::telemetry::TimeSeriesPoint;
+use crate::application::telemetry_repository::{
+    SeriesMetadata, TelemetryRepository, DISCOVERED_MEASUREMENTS,
+};
+use crate::domain::downsampling::Downsampling;
+use crate::domain::telemetry::{TaggedSeries, TimeSeriesPoint};
+use crate::domain::time_range::TimeRange;
+use crate::infrastructure::config::{Backend, InfluxSettings};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use futures::future::join_all;
+use crate::infrastructure::influx_http::InfluxHttpClient;
 use serde::Deserialize;
 
 #[derive(Debug, Clone)]
 pub struct InfluxRepository {
     host: String,
-    token: String,
     database: String,
     retention_policy: String,
+    http: InfluxHttpClient,
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,6 +27,9 @@ struct InfluxQLResponse {
 
 #[derive(Debug, Deserialize)]
 struct InfluxQLResult {
+    /// Position of the statement in a multi-statement query
+    #[serde(default)]
+    statement_id: Option<usize>,
     #[serde(default)]
     series: Option<Vec<InfluxQLSeries>>,
     #[serde(default)]
@@ -37,45 +47,17 @@ struct InfluxQLSeries {
 }
 
 impl InfluxRepository {
-    pub fn new(host: String, token: String, database: String, retention_policy: String) -> Self {
-        Self {
-            host: host.trim_end_matches('/').to_string(),
-            token,
-            database,
-            retention_policy,
-        }
-    }
-
-    fn build_query_url(&self, query: &str) -> Result<String> {
-        let encoded_query = urlencoding::encode(query);
-        Ok(format!(
-            "{}/query?db={}&rp={}&q={}",
-            self.host, self.database, self.retention_policy, encoded_query
-        ))
+    pub fn new(settings: &InfluxSettings) -> Result<Self> {
+        Ok(Self {
+            host: settings.host.trim_end_matches('/').to_string(),
+            database: settings.database.clone(),
+            retention_policy: settings.retention_policy.clone(),
+            http: InfluxHttpClient::new(settings)?,
+        })
     }
 
     async fn execute_query(&self, query: &str) -> Result<InfluxQLResponse> {
-        let url = self.build_query_url(query)?;
-        
-        let client = reqwest::Client::new();
-        let response = client
-            .get(&url)
-            .header("Authorization", format!("Token {}", self.token))
-            .header("Accept", "application/json")
-            .send()
-            .await
-            .context("Failed to send request to InfluxDB")?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("InfluxDB query failed with status {}: {}", status, body);
-        }
-
-        let data = response
-            .json::<InfluxQLResponse>()
-            .await
-            .context("Failed to parse InfluxDB response")?;
+        let data = self.send_query(query).await?;
 
         // Check for errors in the response
         if let Some(result) = data.results.first() {
@@ -86,6 +68,28 @@ impl InfluxRepository {
 
         Ok(data)
     }
+
+    /// Send a (possibly multi-statement) query, retrying transient failures.
+    /// The query goes in a POST form body, so long batches don't run into URL length limits.
+    /// Per-statement errors are left in the results for the caller to inspect.
+    async fn send_query(&self, query: &str) -> Result<InfluxQLResponse> {
+        let url = format!("{}/query", self.host);
+        let form = [
+            ("db", self.database.as_str()),
+            ("rp", self.retention_policy.as_str()),
+            ("q", query),
+        ];
+
+        let response = self
+            .http
+            .send(|client| client.post(&url).header("Accept", "application/json").form(&form))
+            .await?;
+
+        response
+            .json::<InfluxQLResponse>()
+            .await
+            .context("Failed to parse InfluxDB response")
+    }
 }
 
 #[async_trait]
@@ -112,143 +116,291 @@ impl TelemetryRepository for InfluxRepository {
         Ok(hosts)
     }
 
-    async fn get_probe_metadata(&self, aquarium_id: &str, hours: i32) -> Result<Vec<ProbeMetadata>> {
-        // Query for actual data points with the host filter, then extract unique combinations
-        // This ensures we only get probe_type + name pairs that actually have data for this host
-        // Filter based on the selected time range to match what the user is viewing
-        // Note: We must SELECT a field (value), not tags. Tags are returned via GROUP BY.
-        // Note: "name" is a reserved keyword in InfluxDB, so we must quote it in GROUP BY
-        let query = format!(
-            "SELECT value FROM apex_probe WHERE host = '{}' AND time >= now() - {}h GROUP BY probe_type, \"name\" LIMIT 1",
-            aquarium_id, hours
-        );
-
-        tracing::debug!("Executing probe metadata query: {}", query);
-        let response = self.execute_query(&query).await?;
+    async fn get_series_metadata(&self, aquarium_id: &str, range: &TimeRange) -> Result<Vec<SeriesMetadata>> {
+        // One statement per discovered measurement, batched into a single round trip.
+        // Query for actual data points with the host filter, then extract unique tag combinations
+        // from the GROUP BY so we only get series that have data for this host in the selected range
+        // Note: "name" is a reserved keyword in InfluxDB, so tags are quoted
+        let (start, end) = Backend::InfluxQL.time_bounds(range);
+        let host = Backend::InfluxQL.escape_string(aquarium_id);
+        let statements: Vec<String> = DISCOVERED_MEASUREMENTS
+            .iter()
+            .map(|(measurement, tags)| {
+                let group_by: Vec<String> = tags.iter().map(|t| format!("\"{}\"", t)).collect();
+                format!(
+                    "SELECT * FROM \"{}\" WHERE \"host\" = '{}' AND time >= {} AND time <= {} GROUP BY {} LIMIT 1",
+                    measurement,
+                    host,
+                    start,
+                    end,
+                    group_by.join(", ")
+                )
+            })
+            .collect();
+        let query = statements.join("; ");
+
+        tracing::debug!("Executing series metadata query: {}", query);
+        let response = self.send_query(&query).await?;
 
         let mut metadata = Vec::new();
-        if let Some(result) = response.results.first() {
+        for (idx, result) in response.results.iter().enumerate() {
+            let Some((measurement, _)) = DISCOVERED_MEASUREMENTS.get(result.statement_id.unwrap_or(idx)) else {
+                continue;
+            };
+            // A failed statement says nothing about which series exist, so discovery fails
             if let Some(error) = &result.error {
-                tracing::error!("InfluxDB query error: {}", error);
-                return Ok(metadata);
+                anyhow::bail!("InfluxDB series metadata query for {} failed: {}", measurement, error);
             }
 
-            if let Some(series_list) = &result.series {
-                tracing::debug!("Got {} series from InfluxDB for host {}", series_list.len(), aquarium_id);
-
-                for series in series_list {
-                    // Extract probe_type and name from tags
-                    if let Some(tags) = &series.tags {
-                        let probe_type = tags.get("probe_type").map(|s| s.to_string());
-                        let name = tags.get("name").map(|s| s.to_string());
-
-                        if let (Some(pt), Some(n)) = (probe_type, name) {
-                            metadata.push(ProbeMetadata {
-                                probe_type: pt,
-                                name: n,
-                            });
-                        }
-                    }
-                }
+            for series in result.series.iter().flatten() {
+                // Extract the identifying tags from the GROUP BY
+                let Some(tags) = &series.tags else {
+                    continue;
+                };
+                metadata.extend(SeriesMetadata::from_tags(measurement, |key| {
+                    tags.get(key).map(String::as_str)
+                }));
             }
         }
 
-        tracing::debug!("Found {} probe metadata entries for host {}", metadata.len(), aquarium_id);
+        tracing::debug!("Found {} series metadata entries for host {}", metadata.len(), aquarium_id);
         Ok(metadata)
     }
 
     async fn query_single_value(&self, query: &str) -> Result<Option<f64>> {
         let response = self.execute_query(query).await?;
+        Ok(response.results.first().and_then(single_value))
+    }
 
-        if let Some(result) = response.results.first() {
-            if let Some(series) = &result.series {
-                if let Some(s) = series.first() {
-                    if let Some(value_row) = s.values.first() {
-                        // Find the value column (usually index 1 for aggregations)
-                        let value_idx = s.columns.iter().position(|c| c == "mean" || c == "last" || c == "value")
-                            .unwrap_or(1);
-                        
-                        if value_idx < value_row.len() {
-                            if let Some(val) = value_row[value_idx].as_f64() {
-                                return Ok(Some(val));
-                            }
-                        }
-                    }
-                }
-            }
+    async fn query_single_values(&self, queries: &[String]) -> Result<Vec<Result<Option<f64>>>> {
+        if queries.is_empty() {
+            return Ok(Vec::new());
         }
 
-        Ok(None)
+        // InfluxDB runs semicolon-separated statements in order and returns one result per statement
+        let batch = queries
+            .iter()
+            .map(|q| q.trim().trim_end_matches(';'))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        match self.send_query(&batch).await {
+            Ok(response) => Ok(batch_single_values(response, queries.len())),
+            // InfluxDB rejects the whole batch when one statement doesn't parse; run the
+            // queries one by one so the other tiles still get their values. A transport
+            // error would only fail them all again.
+            Err(e) if queries.len() > 1 && e.downcast_ref::<reqwest::Error>().is_none() => {
+                tracing::warn!("Batched tile query failed, running {} queries separately: {:#}", queries.len(), e);
+                Ok(join_all(queries.iter().map(|query| self.query_single_value(query))).await)
+            }
+            Err(e) => Err(e),
+        }
     }
 
     async fn query_time_series_downsampled(
         &self,
         query: &str,
         max_points: usize,
+        downsampling: Downsampling,
     ) -> Result<Vec<TimeSeriesPoint>> {
         let response = self.execute_query(query).await?;
 
-        let mut points = Vec::new();
-        if let Some(result) = response.results.first() {
-            if let Some(series) = &result.series {
-                for s in series {
-                    let time_idx = s.columns.iter().position(|c| c == "time").unwrap_or(0);
-                    let value_idx = s.columns.iter().position(|c| c == "value").unwrap_or(1);
+        let points = response
+            .results
+            .first()
+            .and_then(|result| result.series.as_ref())
+            .into_iter()
+            .flatten()
+            .flat_map(series_points)
+            .collect();
 
-                    for value_row in &s.values {
-                        if value_row.len() > time_idx && value_row.len() > value_idx {
-                            if let (Some(time_str), Some(value)) = (
-                                value_row[time_idx].as_str(),
-                                value_row[value_idx].as_f64(),
-                            ) {
-                                if let Ok(time) = chrono::DateTime::parse_from_rfc3339(time_str) {
-                                    points.push(TimeSeriesPoint::new(
-                                        time.timestamp_millis(),
-                                        value,
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        Ok(downsampling.apply(points, max_points))
+    }
 
-        // Apply server-side downsampling if needed
-        if points.len() > max_points {
-            Ok(Self::downsample_points(points, max_points))
-        } else {
-            Ok(points)
-        }
+    async fn query_tagged_series(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> Result<Vec<TaggedSeries>> {
+        let response = self.execute_query(query).await?;
+        Ok(tagged_series(&response, max_points, downsampling))
     }
 }
 
-impl InfluxRepository {
-    /// Downsample time series points using bucket averaging
-    fn downsample_points(points: Vec<TimeSeriesPoint>, max_points: usize) -> Vec<TimeSeriesPoint> {
-        if points.is_empty() || points.len() <= max_points {
-            return points;
-        }
+/// One downsampled series per InfluxQL result series, i.e. per GROUP BY tag combination
+fn tagged_series(response: &InfluxQLResponse, max_points: usize, downsampling: Downsampling) -> Vec<TaggedSeries> {
+    response
+        .results
+        .first()
+        .and_then(|result| result.series.as_ref())
+        .into_iter()
+        .flatten()
+        .map(|s| {
+            let tags = s.tags.iter().flatten().map(|(k, v)| (k.clone(), v.clone())).collect();
+            TaggedSeries::new(tags, downsampling.apply(series_points(s), max_points))
+        })
+        .collect()
+}
 
-        let bucket_size = (points.len() as f64 / max_points as f64).ceil() as usize;
-        let mut downsampled = Vec::with_capacity(max_points);
+/// Points of one result series, from its `time` and `value` columns
+fn series_points(s: &InfluxQLSeries) -> Vec<TimeSeriesPoint> {
+    let time_idx = s.columns.iter().position(|c| c == "time").unwrap_or(0);
+    let value_idx = s.columns.iter().position(|c| c == "value").unwrap_or(1);
+
+    s.values
+        .iter()
+        .filter_map(|row| {
+            let time = chrono::DateTime::parse_from_rfc3339(row.get(time_idx)?.as_str()?).ok()?;
+            Some(TimeSeriesPoint::new(time.timestamp_millis(), row.get(value_idx)?.as_f64()?))
+        })
+        .collect()
+}
 
-        for chunk_start in (0..points.len()).step_by(bucket_size) {
-            let chunk_end = std::cmp::min(chunk_start + bucket_size, points.len());
-            let chunk = &points[chunk_start..chunk_end];
+/// Extract the value of a single-value (tile) query result
+fn single_value(result: &InfluxQLResult) -> Option<f64> {
+    let s = result.series.as_ref()?.first()?;
+    let value_row = s.values.first()?;
 
-            if chunk.is_empty() {
-                continue;
-            }
+    // Find the value column (usually index 1 for aggregations)
+    let value_idx = s
+        .columns
+        .iter()
+        .position(|c| c == "mean" || c == "last" || c == "value")
+        .unwrap_or(1);
+
+    value_row.get(value_idx)?.as_f64()
+}
 
-            // Use middle point's timestamp and average value
-            let mid_idx = chunk.len() / 2;
-            let avg_value = chunk.iter().map(|p| p.value).sum::<f64>() / chunk.len() as f64;
+/// Map each statement result of a batched query back to its position in the batch
+fn batch_single_values(response: InfluxQLResponse, count: usize) -> Vec<Result<Option<f64>>> {
+    let mut values: Vec<Result<Option<f64>>> = (0..count)
+        .map(|i| Err(anyhow::anyhow!("InfluxDB returned no result for statement {}", i)))
+        .collect();
 
-            downsampled.push(TimeSeriesPoint::new(chunk[mid_idx].time_ms, avg_value));
+    for (idx, result) in response.results.into_iter().enumerate() {
+        let statement_id = result.statement_id.unwrap_or(idx);
+        if statement_id >= count {
+            continue;
         }
 
-        downsampled
+        values[statement_id] = match &result.error {
+            Some(error) => Err(anyhow::anyhow!("InfluxDB query error: {}", error)),
+            None => Ok(single_value(&result)),
+        };
     }
+
+    values
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Form, Json, Router};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    /// Answers `SELECT <n>` statements with the value n; like InfluxDB, any statement that
+    /// doesn't parse fails the whole request
+    async fn stub_server() -> String {
+        let app = Router::new().route(
+            "/query",
+            post(|Form(form): Form<HashMap<String, String>>| async move {
+                assert_eq!(form.get("db").map(String::as_str), Some("neptune"));
+                let mut results = Vec::new();
+                for (idx, statement) in form["q"].split("; ").enumerate() {
+                    let Some(value) = statement.strip_prefix("SELECT ").and_then(|v| v.parse::<f64>().ok()) else {
+                        let error = json!({"error": format!("error parsing query: {}", statement)});
+                        return (axum::http::StatusCode::BAD_REQUEST, Json(error));
+                    };
+                    results.push(json!({"statement_id": idx, "series": [
+                        {"name": "apex_probe", "columns": ["time", "last"], "values": [["2025-01-01T00:00:00Z", value]]}
+                    ]}));
+                }
+                (axum::http::StatusCode::OK, Json(json!({ "results": results })))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    fn repository(host: String) -> InfluxRepository {
+        InfluxRepository::new(&InfluxSettings {
+            host,
+            token: "token".to_string(),
+            token_file: None,
+            database: "neptune".to_string(),
+            retention_policy: "autogen".to_string(),
+            backend: Backend::InfluxQL,
+            org: None,
+            bucket: None,
+            connect_timeout_ms: 1_000,
+            request_timeout_ms: 1_000,
+            max_retries: 0,
+            retry_backoff_ms: 0,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_invalid_statement_does_not_fail_the_batch() {
+        let repo = repository(stub_server().await);
+
+        let valid = repo
+            .query_single_values(&["SELECT 78.4".to_string(), "SELECT 8.1".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(valid[0].as_ref().unwrap(), &Some(78.4));
+        assert_eq!(valid[1].as_ref().unwrap(), &Some(8.1));
+
+        let queries = ["SELECT 78.4", "SELEC 8.1", "SELECT 412"].map(String::from);
+        let values = repo.query_single_values(&queries).await.unwrap();
+        assert_eq!(values[0].as_ref().unwrap(), &Some(78.4));
+        assert!(values[1].is_err());
+        assert_eq!(values[2].as_ref().unwrap(), &Some(412.0));
+    }
+
+    #[test]
+    fn test_batch_single_values_maps_statements() {
+        let response: InfluxQLResponse = serde_json::from_str(
+            r#"{"results": [
+                {"statement_id": 2, "series": [{"name": "apex_probe", "columns": ["time", "last"], "values": [["2025-01-01T00:00:00Z", 8.1]]}]},
+                {"statement_id": 0, "series": [{"name": "apex_probe", "columns": ["time", "last"], "values": [["2025-01-01T00:00:00Z", 78.4]]}]},
+                {"statement_id": 1, "error": "field not found"},
+                {"statement_id": 3}
+            ]}"#,
+        )
+        .unwrap();
+
+        let values = batch_single_values(response, 5);
+
+        assert_eq!(values[0].as_ref().unwrap(), &Some(78.4));
+        assert!(values[1].is_err());
+        assert_eq!(values[2].as_ref().unwrap(), &Some(8.1));
+        assert_eq!(values[3].as_ref().unwrap(), &None);
+        assert!(values[4].is_err());
+    }
+
+    #[test]
+    fn test_tagged_series_keeps_group_by_tags() {
+        let response: InfluxQLResponse = serde_json::from_str(
+            r#"{"results": [{"statement_id": 0, "series": [
+                {"name": "apex_probe", "tags": {"name": "pH"}, "columns": ["time", "value"],
+                 "values": [["2025-01-01T00:00:00Z", 8.1], ["2025-01-01T00:01:00Z", 8.2]]},
+                {"name": "apex_probe", "tags": {"name": "pHx3"}, "columns": ["time", "value"],
+                 "values": [["2025-01-01T00:00:00Z", 8.3], ["2025-01-01T00:01:00Z", null]]}
+            ]}]}"#,
+        )
+        .unwrap();
+
+        let series = tagged_series(&response, 150, Downsampling::Average);
+
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].tag("name"), Some("pH"));
+        assert_eq!(series[0].points.len(), 2);
+        assert_eq!(series[1].tag("name"), Some("pHx3"));
+        assert_eq!(series[1].points.len(), 1);
+    }
+}
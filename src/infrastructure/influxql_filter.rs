@@ -0,0 +1,502 @@
+// InfluxQL filter parser - Extracts the measurement and tag predicates from a query
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+
+/// What a query selects from, as far as tag matching is concerned
+#[derive(Debug, Clone)]
+pub struct QueryFilter {
+    pub measurement: String,
+    pub condition: Condition,
+}
+
+/// WHERE clause, reduced to tag predicates combined with AND/OR
+#[derive(Debug, Clone)]
+pub enum Condition {
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Tag { key: String, matcher: TagMatcher },
+    /// Field, time or other comparison that doesn't constrain tags
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub enum TagMatcher {
+    Equals(String),
+    NotEquals(String),
+    Matches(Regex),
+    NotMatches(Regex),
+}
+
+impl TagMatcher {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            TagMatcher::Equals(expected) => value == expected,
+            TagMatcher::NotEquals(expected) => value != expected,
+            TagMatcher::Matches(regex) => regex.is_match(value),
+            TagMatcher::NotMatches(regex) => !regex.is_match(value),
+        }
+    }
+}
+
+impl Condition {
+    /// Whether a series with these tags can satisfy the condition. `tag` looks up a
+    /// tag value; predicates on tags it doesn't know are assumed to hold.
+    pub fn matches<'t>(&self, tag: &dyn Fn(&str) -> Option<&'t str>) -> bool {
+        match self {
+            Condition::And(conditions) => conditions.iter().all(|c| c.matches(tag)),
+            Condition::Or(conditions) => conditions.iter().any(|c| c.matches(tag)),
+            Condition::Tag { key, matcher } => tag(key).is_none_or(|value| matcher.matches(value)),
+            Condition::Other => true,
+        }
+    }
+}
+
+/// Parse the measurement and WHERE clause of a SELECT statement
+pub fn parse(query: &str) -> Result<QueryFilter> {
+    let tokens = tokenize(query)?;
+
+    let from = top_level_keyword(&tokens, 0, "FROM").ok_or_else(|| anyhow!("no FROM clause"))?;
+    let measurement = measurement(&tokens[from + 1..])?;
+
+    let condition = match top_level_keyword(&tokens, from, "WHERE") {
+        Some(start) => {
+            let end = CLAUSE_ENDS
+                .iter()
+                .filter_map(|k| top_level_keyword(&tokens, start, k))
+                .min()
+                .unwrap_or(tokens.len());
+            let mut parser = Parser {
+                tokens: &tokens[start + 1..end],
+                pos: 0,
+            };
+            let condition = parser.expression()?;
+            if parser.pos < parser.tokens.len() {
+                bail!("unexpected {:?} in WHERE clause", parser.tokens[parser.pos]);
+            }
+            condition
+        }
+        None => Condition::Other,
+    };
+
+    Ok(QueryFilter {
+        measurement,
+        condition,
+    })
+}
+
+/// Mistakes InfluxDB would reject the statement for: an unterminated string, identifier,
+/// regex or template variable, a character that isn't InfluxQL, unbalanced parentheses,
+/// or a SELECT without a FROM clause. Unlike `parse`, valid queries the filter analysis
+/// doesn't support pass.
+pub fn check_syntax(query: &str) -> Result<()> {
+    let tokens = tokenize(query)?;
+
+    let mut depth = 0;
+    for token in &tokens {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen if depth == 0 => bail!("unbalanced ')'"),
+            Token::RParen => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        bail!("unclosed '('");
+    }
+
+    if !tokens.first().is_some_and(|t| t.is_keyword("SELECT")) {
+        bail!("not a SELECT statement");
+    }
+    match top_level_keyword(&tokens, 0, "FROM") {
+        Some(from) if from + 1 < tokens.len() => Ok(()),
+        Some(_) => bail!("missing measurement after FROM"),
+        None => bail!("no FROM clause"),
+    }
+}
+
+/// Keywords that end a WHERE clause
+const CLAUSE_ENDS: [&str; 7] = ["GROUP", "ORDER", "LIMIT", "OFFSET", "SLIMIT", "SOFFSET", "TZ"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Bare or double-quoted identifier; keywords are bare identifiers
+    Ident { name: String, quoted: bool },
+    Str(String),
+    Regex(String),
+    /// Number or duration literal
+    Number(String),
+    /// `${name}` template variable outside a string
+    Template(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+    Dot,
+}
+
+impl Token {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Ident { name, quoted: false } if name.eq_ignore_ascii_case(keyword))
+    }
+}
+
+/// Position of `keyword` at paren depth 0, at or after `from`
+fn top_level_keyword(tokens: &[Token], from: usize, keyword: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            t if i >= from && depth == 0 && t.is_keyword(keyword) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Measurement name, the last part of `db.rp.measurement`
+fn measurement(tokens: &[Token]) -> Result<String> {
+    let mut name = None;
+    let mut expect_name = true;
+
+    for token in tokens {
+        match token {
+            Token::Ident { name: part, .. } if expect_name => {
+                name = Some(part.clone());
+                expect_name = false;
+            }
+            Token::Dot if !expect_name => expect_name = true,
+            Token::Regex(_) => bail!("regex measurements are not supported"),
+            Token::LParen => bail!("subqueries are not supported"),
+            _ => break,
+        }
+    }
+
+    name.ok_or_else(|| anyhow!("missing measurement"))
+}
+
+const OPERATORS: [&str; 18] = [
+    "=~", "!~", "!=", "<>", "<=", ">=", "::", "=", "<", ">", "+", "-", "*", "/", "%", "&", "|", "^",
+];
+
+fn tokenize(query: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ';' => break,
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '.' if !chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                tokens.push(Token::Dot);
+                i += 1;
+            }
+            '"' | '\'' => {
+                let (text, next) = quoted(&chars, i)?;
+                tokens.push(if c == '"' {
+                    Token::Ident { name: text, quoted: true }
+                } else {
+                    Token::Str(text)
+                });
+                i = next;
+            }
+            // After a regex operator or FROM, a slash starts a regex literal rather than division
+            '/' if matches!(tokens.last(), Some(Token::Op("=~" | "!~")))
+                || tokens.last().is_some_and(|t| t.is_keyword("FROM")) =>
+            {
+                let (text, next) = quoted(&chars, i)?;
+                tokens.push(Token::Regex(text));
+                i = next;
+            }
+            '$' if chars.get(i + 1) == Some(&'{') => {
+                let end = (i + 2..chars.len())
+                    .find(|&j| chars[j] == '}')
+                    .ok_or_else(|| anyhow!("unterminated template variable"))?;
+                tokens.push(Token::Template(chars[i + 2..end].iter().collect()));
+                i = end + 1;
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let end = (i..chars.len())
+                    .find(|&j| !(chars[j].is_ascii_alphanumeric() || chars[j] == '.'))
+                    .unwrap_or(chars.len());
+                tokens.push(Token::Number(chars[i..end].iter().collect()));
+                i = end;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let end = (i..chars.len())
+                    .find(|&j| !(chars[j].is_alphanumeric() || chars[j] == '_'))
+                    .unwrap_or(chars.len());
+                tokens.push(Token::Ident {
+                    name: chars[i..end].iter().collect(),
+                    quoted: false,
+                });
+                i = end;
+            }
+            _ => {
+                let rest: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+                let op = OPERATORS
+                    .iter()
+                    .find(|op| rest.starts_with(**op))
+                    .ok_or_else(|| anyhow!("unexpected character {:?}", c))?;
+                tokens.push(Token::Op(op));
+                i += op.len();
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Read a literal delimited by `chars[start]`, handling backslash escapes
+fn quoted(chars: &[char], start: usize) -> Result<(String, usize)> {
+    let delimiter = chars[start];
+    let mut text = String::new();
+    let mut i = start + 1;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                let escaped = chars[i + 1];
+                match (delimiter, escaped) {
+                    ('/', '/') => text.push('/'),
+                    // Regexes keep their own escapes
+                    ('/', other) => {
+                        text.push('\\');
+                        text.push(other);
+                    }
+                    (_, 'n') => text.push('\n'),
+                    (_, other) => text.push(other),
+                }
+                i += 2;
+            }
+            c if c == delimiter => return Ok((text, i + 1)),
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    bail!("unterminated literal starting with {}", delimiter)
+}
+
+/// Recursive-descent parser for WHERE conditions
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn expression(&mut self) -> Result<Condition> {
+        let mut terms = vec![self.conjunction()?];
+        while self.peek().is_some_and(|t| t.is_keyword("OR")) {
+            self.pos += 1;
+            terms.push(self.conjunction()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { Condition::Or(terms) })
+    }
+
+    fn conjunction(&mut self) -> Result<Condition> {
+        let mut terms = vec![self.primary()?];
+        while self.peek().is_some_and(|t| t.is_keyword("AND")) {
+            self.pos += 1;
+            terms.push(self.primary()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { Condition::And(terms) })
+    }
+
+    fn primary(&mut self) -> Result<Condition> {
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            let condition = self.expression()?;
+            if self.next() != Some(&Token::RParen) {
+                bail!("missing closing parenthesis");
+            }
+            return Ok(condition);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Condition> {
+        let left = self.operand()?;
+        let op = match self.next() {
+            Some(Token::Op(op @ ("=" | "!=" | "<>" | "=~" | "!~" | "<" | "<=" | ">" | ">="))) => *op,
+            other => bail!("expected a comparison operator, found {:?}", other),
+        };
+        let right = self.operand()?;
+
+        // Only `tag op 'string'` and `tag op /regex/` constrain tags
+        let key = match left.as_slice() {
+            [Token::Ident { name, .. }] if !name.eq_ignore_ascii_case("time") => name.clone(),
+            _ => return Ok(Condition::Other),
+        };
+        let matcher = match (op, right.as_slice()) {
+            ("=", [Token::Str(value)]) => TagMatcher::Equals(value.clone()),
+            ("!=" | "<>", [Token::Str(value)]) => TagMatcher::NotEquals(value.clone()),
+            ("=~", [Token::Regex(pattern)]) => TagMatcher::Matches(Regex::new(pattern)?),
+            ("!~", [Token::Regex(pattern)]) => TagMatcher::NotMatches(Regex::new(pattern)?),
+            _ => return Ok(Condition::Other),
+        };
+        Ok(Condition::Tag { key, matcher })
+    }
+
+    /// Tokens of one side of a comparison: values joined by arithmetic, function calls and casts
+    fn operand(&mut self) -> Result<Vec<Token>> {
+        let start = self.pos;
+        loop {
+            // Unary sign
+            if matches!(self.peek(), Some(Token::Op("-" | "+"))) {
+                self.pos += 1;
+            }
+
+            match self.next().cloned() {
+                Some(Token::Ident { .. }) if self.peek() == Some(&Token::LParen) => self.skip_group()?,
+                Some(Token::Ident { .. } | Token::Str(_) | Token::Regex(_) | Token::Number(_) | Token::Template(_)) => {}
+                Some(Token::LParen) => {
+                    self.pos -= 1;
+                    self.skip_group()?;
+                }
+                other => bail!("expected a value, found {:?}", other),
+            }
+
+            match self.peek() {
+                Some(Token::Op("::")) => {
+                    self.pos += 1;
+                    match self.next() {
+                        Some(Token::Ident { .. }) => {}
+                        other => bail!("expected a type after ::, found {:?}", other),
+                    }
+                    if !matches!(self.peek(), Some(Token::Op("+" | "-" | "*" | "/" | "%"))) {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                Some(Token::Op("+" | "-" | "*" | "/" | "%")) => self.pos += 1,
+                _ => break,
+            }
+        }
+
+        // Casts don't change what is compared
+        Ok(self.tokens[start..self.pos]
+            .iter()
+            .take_while(|t| **t != Token::Op("::"))
+            .cloned()
+            .collect())
+    }
+
+    /// Skip a balanced parenthesized group starting at the current token
+    fn skip_group(&mut self) -> Result<()> {
+        let mut depth = 0;
+        while let Some(token) = self.next() {
+            match token {
+                Token::LParen => depth += 1,
+                Token::RParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+        bail!("missing closing parenthesis")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe<'t>(probe_type: &'t str, name: &'t str) -> impl Fn(&str) -> Option<&'t str> {
+        move |key| match key {
+            "probe_type" => Some(probe_type),
+            "name" => Some(name),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_widget_queries() {
+        let filter = parse(
+            "SELECT LAST(\"value\") AS value FROM \"apex_probe\" WHERE \"host\"='${source}' AND \"probe_type\"='temp' AND \"name\"='Tmp' AND \"value\" >= 50 AND time >= now() - 6h AND time <= now() GROUP BY time(${interval}) fill(none)",
+        )
+        .unwrap();
+
+        assert_eq!(filter.measurement, "apex_probe");
+        assert!(filter.condition.matches(&probe("temp", "Tmp")));
+        assert!(!filter.condition.matches(&probe("temp", "Tmpx3")));
+        assert!(!filter.condition.matches(&probe("ph", "Tmp")));
+
+        let overlay = parse(
+            "SELECT CASE WHEN state_code = 0 OR state_code = 1 THEN 1 ELSE 0 END AS value FROM \"apex_output\" WHERE \"host\"='${source}' AND \"name\"='ATO' AND time >= ${start} AND time <= ${end}",
+        )
+        .unwrap();
+        assert_eq!(overlay.measurement, "apex_output");
+    }
+
+    #[test]
+    fn test_predicate_forms() {
+        // A double-quoted right-hand side is an identifier, not a string, so it doesn't constrain tags
+        let filter = parse(
+            "SELECT value FROM neptune.autogen.apex_probe WHERE (probe_type = \"temp\" OR probe_type='ph') AND name =~ /^Tmp(x\\d+)?$/ AND \"host\"::tag='a\\'b' AND value > -1",
+        )
+        .unwrap();
+        assert_eq!(filter.measurement, "apex_probe");
+        assert!(filter.condition.matches(&probe("temp", "Tmpx17")));
+        assert!(filter.condition.matches(&probe("ph", "Tmp")));
+        assert!(!filter.condition.matches(&probe("ph", "pH")));
+
+        let filter = parse("SELECT value FROM apex_probe WHERE probe_type != 'temp' AND name !~ /x/").unwrap();
+        assert!(filter.condition.matches(&probe("ph", "pH")));
+        assert!(!filter.condition.matches(&probe("ph", "pHx3")));
+        assert!(!filter.condition.matches(&probe("temp", "Tmp")));
+    }
+
+    #[test]
+    fn test_unanalyzable_queries() {
+        assert!(parse("SHOW TAG VALUES WITH KEY = host").is_err());
+        assert!(parse("SELECT value FROM /apex_.*/ WHERE name = 'x'").is_err());
+        assert!(parse("SELECT value FROM apex_probe WHERE name = 'x").is_err());
+        assert!(parse("SELECT value FROM apex_probe WHERE (name = 'x'").is_err());
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        // Valid InfluxQL the filter analysis doesn't support
+        assert!(check_syntax("SELECT value FROM /^apex_(probe|output)$/ WHERE name = 'x'").is_ok());
+        assert!(check_syntax("SELECT value FROM (SELECT value FROM apex_probe) WHERE value & 1 = 1").is_ok());
+
+        assert!(check_syntax("SELECT value FROM apex_probe WHERE name = 'x").is_err());
+        assert!(check_syntax("SELECT value FROM \"apex_probe WHERE name = 'x'").is_err());
+        assert!(check_syntax("SELECT value FROM apex_probe WHERE (name = 'x'").is_err());
+        assert!(check_syntax("SELECT value FROM apex_probe WHERE name = 'x')").is_err());
+        assert!(check_syntax("SELECT value WHERE name = 'x'").is_err());
+        assert!(check_syntax("SELECT value FROM").is_err());
+        assert!(check_syntax("SELEC value FROM apex_probe").is_err());
+        assert!(check_syntax("SELECT value FROM apex_probe WHERE host = '${source}' AND time >= ${start").is_err());
+    }
+}
@@ -1,7 +1,16 @@
 // Infrastructure layer - External dependencies and adapters
 pub mod chunked_thrift;
+pub mod cli;
 pub mod config;
+pub mod config_check;
+pub mod config_reload;
+pub mod fixture_repository;
+pub mod flux_repository;
 pub mod http_response;
+pub mod influx_http;
 pub mod influx_repository;
+pub mod influxql_filter;
+pub mod query_builder;
+pub mod sql_repository;
 pub mod thrift_mapper;
 
@@ -0,0 +1,230 @@
+// Query builder - Compiles structured widget queries to InfluxQL
+use crate::infrastructure::config::Backend;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+
+/// Structured widget query: what to select, from where, and how to aggregate it.
+/// The host filter (`${source}`) and time bounds are added by the builder.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct QuerySpec {
+    pub measurement: String,
+    #[serde(default = "default_field")]
+    pub field: String,
+    /// Tag filters, e.g. `{ probe_type = "temp", name = "Tmp" }`
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub aggregate: Aggregate,
+    /// Inclusive lower bound on the field value (drops sensor glitches)
+    pub min: Option<f64>,
+    /// Inclusive upper bound on the field value
+    pub max: Option<f64>,
+    /// How empty GROUP BY buckets are filled (charts only)
+    #[serde(default)]
+    pub fill: Fill,
+    /// How far back a tile looks for its value, as an InfluxQL duration (e.g. `5m`)
+    #[serde(default = "default_window", deserialize_with = "deserialize_window")]
+    pub window: String,
+}
+
+fn default_field() -> String {
+    "value".to_string()
+}
+
+fn default_window() -> String {
+    "5m".to_string()
+}
+
+/// InfluxQL duration units
+const DURATION_UNITS: [&str; 8] = ["ns", "u", "ms", "s", "m", "h", "d", "w"];
+
+/// A single InfluxQL duration literal: digits followed by a unit
+fn deserialize_window<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let window = String::deserialize(deserializer)?;
+    let unit = window.trim_start_matches(|c: char| c.is_ascii_digit());
+    if unit.len() == window.len() || !DURATION_UNITS.contains(&unit) {
+        return Err(serde::de::Error::custom(format!(
+            "invalid window {:?}, expected a duration such as \"5m\" (units: {})",
+            window,
+            DURATION_UNITS.join(", ")
+        )));
+    }
+    Ok(window)
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    #[default]
+    Last,
+    First,
+    Mean,
+    Median,
+    Min,
+    Max,
+    Sum,
+    Count,
+}
+
+impl Aggregate {
+    fn function(self) -> &'static str {
+        match self {
+            Aggregate::Last => "LAST",
+            Aggregate::First => "FIRST",
+            Aggregate::Mean => "MEAN",
+            Aggregate::Median => "MEDIAN",
+            Aggregate::Min => "MIN",
+            Aggregate::Max => "MAX",
+            Aggregate::Sum => "SUM",
+            Aggregate::Count => "COUNT",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Fill {
+    #[default]
+    None,
+    Null,
+    Previous,
+    Linear,
+    Zero,
+}
+
+impl Fill {
+    fn option(self) -> &'static str {
+        match self {
+            Fill::None => "none",
+            Fill::Null => "null",
+            Fill::Previous => "previous",
+            Fill::Linear => "linear",
+            Fill::Zero => "0",
+        }
+    }
+}
+
+impl QuerySpec {
+    /// Latest value within `window`, for a tile
+    pub fn tile_query(&self) -> String {
+        format!(
+            "SELECT {}({}) FROM {} WHERE {} AND time >= now() - {}",
+            self.aggregate.function(),
+            identifier(&self.field),
+            identifier(&self.measurement),
+            self.conditions(),
+            self.window
+        )
+    }
+
+    /// Aggregated series over the requested range, for a chart
+    pub fn series_query(&self) -> String {
+        self.bucketed_query("")
+    }
+
+    /// Like `series_query`, with one series per value of `tag`, for a series group
+    pub fn grouped_series_query(&self, tag: &str) -> String {
+        self.bucketed_query(&format!(", {}", identifier(tag)))
+    }
+
+    fn bucketed_query(&self, group_by: &str) -> String {
+        format!(
+            "SELECT {}({}) AS value FROM {} WHERE {} AND time >= ${{start}} AND time <= ${{end}} GROUP BY time(${{interval}}){} fill({})",
+            self.aggregate.function(),
+            identifier(&self.field),
+            identifier(&self.measurement),
+            self.conditions(),
+            group_by,
+            self.fill.option()
+        )
+    }
+
+    fn conditions(&self) -> String {
+        let mut conditions = vec!["\"host\"='${source}'".to_string()];
+        conditions.extend(self.tags.iter().map(|(key, value)| {
+            format!("{}='{}'", identifier(key), Backend::InfluxQL.escape_string(value))
+        }));
+        if let Some(min) = self.min {
+            conditions.push(format!("{} >= {}", identifier(&self.field), min));
+        }
+        if let Some(max) = self.max {
+            conditions.push(format!("{} <= {}", identifier(&self.field), max));
+        }
+        conditions.join(" AND ")
+    }
+}
+
+/// Double-quoted InfluxQL identifier
+fn identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(toml: &str) -> QuerySpec {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_tile_query() {
+        let spec = spec(
+            r#"
+            measurement = "apex_probe"
+            tags = { probe_type = "temp", name = "Tmp" }
+            "#,
+        );
+
+        assert_eq!(
+            spec.tile_query(),
+            "SELECT LAST(\"value\") FROM \"apex_probe\" WHERE \"host\"='${source}' AND \"name\"='Tmp' AND \"probe_type\"='temp' AND time >= now() - 5m"
+        );
+    }
+
+    #[test]
+    fn test_series_query() {
+        let spec = spec(
+            r#"
+            measurement = "apex_cor"
+            field = "load_pct"
+            aggregate = "mean"
+            tags = { name = "Return'Pump" }
+            min = 0
+            max = 100.5
+            fill = "previous"
+            "#,
+        );
+
+        assert_eq!(
+            spec.series_query(),
+            "SELECT MEAN(\"load_pct\") AS value FROM \"apex_cor\" WHERE \"host\"='${source}' AND \"name\"='Return\\'Pump' AND \"load_pct\" >= 0 AND \"load_pct\" <= 100.5 AND time >= ${start} AND time <= ${end} GROUP BY time(${interval}) fill(previous)"
+        );
+    }
+
+    #[test]
+    fn test_grouped_series_query() {
+        let spec = spec(
+            r#"
+            measurement = "apex_probe"
+            tags = { probe_type = "ph" }
+            "#,
+        );
+
+        assert_eq!(
+            spec.grouped_series_query("name"),
+            "SELECT LAST(\"value\") AS value FROM \"apex_probe\" WHERE \"host\"='${source}' AND \"probe_type\"='ph' AND time >= ${start} AND time <= ${end} GROUP BY time(${interval}), \"name\" fill(none)"
+        );
+    }
+
+    #[test]
+    fn test_window_must_be_a_duration() {
+        let parse = |window: &str| toml::from_str::<QuerySpec>(&format!("measurement = \"m\"\nwindow = {:?}", window));
+
+        assert_eq!(parse("90s").unwrap().window, "90s");
+        assert_eq!(parse("1500ms").unwrap().window, "1500ms");
+        for window in ["", "m", "5", "5 m", "5min", "-5m", "5m OR true", "5m; DROP MEASUREMENT apex_probe"] {
+            assert!(parse(window).is_err(), "{:?} accepted", window);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+// InfluxDB 3 SQL repository implementation
+use crate::application::telemetry_repository::{
+    SeriesMetadata, TelemetryRepository, DISCOVERED_MEASUREMENTS,
+};
+use crate::domain::downsampling::Downsampling;
+use crate::domain::telemetry::{TaggedSeries, TimeSeriesPoint};
+use crate::domain::time_range::TimeRange;
+use crate::infrastructure::config::{Backend, InfluxSettings};
+use crate::infrastructure::influx_http::InfluxHttpClient;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// One result row, keyed by column name
+type SqlRow = Map<String, Value>;
+
+#[derive(Debug, Clone)]
+pub struct SqlRepository {
+    host: String,
+    database: String,
+    http: InfluxHttpClient,
+}
+
+impl SqlRepository {
+    pub fn new(settings: &InfluxSettings) -> Result<Self> {
+        Ok(Self {
+            host: settings.host.trim_end_matches('/').to_string(),
+            database: settings.database.clone(),
+            http: InfluxHttpClient::new(settings)?,
+        })
+    }
+
+    async fn execute_query(&self, sql: &str) -> Result<Vec<SqlRow>> {
+        let url = format!("{}/api/v3/query_sql", self.host);
+        let request = serde_json::json!({
+            "db": self.database,
+            "q": sql,
+            "format": "jsonl",
+        });
+
+        let response = self
+            .http
+            .send(|client| client.post(&url).json(&request))
+            .await?;
+
+        let body = response
+            .text()
+            .await
+            .context("Failed to read InfluxDB response")?;
+        parse_rows(&body)
+    }
+}
+
+/// Parse a JSONL body (one object per line) or a JSON array of objects
+fn parse_rows(body: &str) -> Result<Vec<SqlRow>> {
+    if body.trim_start().starts_with('[') {
+        return serde_json::from_str(body).context("Failed to parse InfluxDB JSON response");
+    }
+
+    body.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).context("Failed to parse InfluxDB JSONL row"))
+        .collect()
+}
+
+/// Quote a value as a SQL string literal
+fn sql_string(value: &str) -> String {
+    format!("'{}'", Backend::Sql.escape_string(value))
+}
+
+/// The `value` column, or else the first numeric column other than `time`
+fn row_value(row: &SqlRow) -> Option<f64> {
+    if let Some(value) = row.get("value") {
+        return value.as_f64();
+    }
+    row.iter()
+        .filter(|(column, _)| column.as_str() != "time")
+        .find_map(|(_, value)| value.as_f64())
+}
+
+/// Row timestamp in milliseconds. InfluxDB 3 renders timestamps as RFC 3339
+/// without an offset (UTC); integer nanoseconds are accepted as well.
+fn row_time_ms(row: &SqlRow) -> Option<i64> {
+    match row.get("time")? {
+        Value::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+            .map(|t| t.timestamp_millis())
+            .or_else(|_| {
+                chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+                    .map(|t| t.and_utc().timestamp_millis())
+            })
+            .ok(),
+        Value::Number(n) => n.as_i64().map(|ns| ns / 1_000_000),
+        _ => None,
+    }
+}
+
+fn row_point(row: &SqlRow) -> Option<TimeSeriesPoint> {
+    Some(TimeSeriesPoint::new(row_time_ms(row)?, row_value(row)?))
+}
+
+/// String columns other than `time`, which is how tags come back from a SQL query
+fn row_tags(row: &SqlRow) -> BTreeMap<String, String> {
+    row.iter()
+        .filter(|(column, _)| column.as_str() != "time")
+        .filter_map(|(column, value)| Some((column.clone(), value.as_str()?.to_string())))
+        .collect()
+}
+
+fn row_string(row: &SqlRow, column: &str) -> Option<String> {
+    row.get(column)?.as_str().map(str::to_string)
+}
+
+#[async_trait]
+impl TelemetryRepository for SqlRepository {
+    async fn list_aquarium_ids(&self) -> Result<Vec<String>> {
+        let sql = "SELECT DISTINCT host FROM apex_probe WHERE time >= now() - INTERVAL '30 days' ORDER BY host";
+        let rows = self.execute_query(sql).await?;
+
+        Ok(rows.iter().filter_map(|r| row_string(r, "host")).collect())
+    }
+
+    async fn get_series_metadata(&self, aquarium_id: &str, range: &TimeRange) -> Result<Vec<SeriesMetadata>> {
+        // Tables have different columns, so each measurement gets its own query. Any failure,
+        // even a missing table, fails discovery rather than passing for a measurement without series
+        let (start, end) = Backend::Sql.time_bounds(range);
+        let queries = DISCOVERED_MEASUREMENTS.iter().map(|(measurement, tags)| {
+            let sql = format!(
+                "SELECT DISTINCT {} FROM {} WHERE host = {} AND time >= {} AND time <= {}",
+                tags.join(", "),
+                measurement,
+                sql_string(aquarium_id),
+                start,
+                end
+            );
+            async move {
+                tracing::debug!("Executing series metadata query: {}", sql);
+                (*measurement, self.execute_query(&sql).await)
+            }
+        });
+
+        let mut series = BTreeSet::new();
+        for (measurement, rows) in join_all(queries).await {
+            let rows = rows.with_context(|| format!("Series metadata query for {} failed", measurement))?;
+            series.extend(rows.iter().filter_map(|r| {
+                SeriesMetadata::from_tags(measurement, |key| r.get(key)?.as_str())
+            }));
+        }
+
+        Ok(series.into_iter().collect())
+    }
+
+    async fn query_single_value(&self, query: &str) -> Result<Option<f64>> {
+        let rows = self.execute_query(query).await?;
+        Ok(rows.iter().rev().find_map(row_value))
+    }
+
+    async fn query_time_series_downsampled(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> Result<Vec<TimeSeriesPoint>> {
+        let rows = self.execute_query(query).await?;
+
+        let mut points: Vec<TimeSeriesPoint> = rows.iter().filter_map(row_point).collect();
+        points.sort_by_key(|p| p.time_ms);
+
+        Ok(downsampling.apply(points, max_points))
+    }
+
+    async fn query_tagged_series(
+        &self,
+        query: &str,
+        max_points: usize,
+        downsampling: Downsampling,
+    ) -> Result<Vec<TaggedSeries>> {
+        let rows = self.execute_query(query).await?;
+
+        let mut grouped: BTreeMap<BTreeMap<String, String>, Vec<TimeSeriesPoint>> = BTreeMap::new();
+        for row in &rows {
+            if let Some(point) = row_point(row) {
+                grouped.entry(row_tags(row)).or_default().push(point);
+            }
+        }
+
+        Ok(grouped
+            .into_iter()
+            .map(|(tags, mut points)| {
+                points.sort_by_key(|p| p.time_ms);
+                TaggedSeries::new(tags, downsampling.apply(points, max_points))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::config::Backend;
+    use axum::{routing::post, Json, Router};
+
+    /// Serve `/api/v3/query_sql` locally, answering every query with `body`
+    async fn stub_server(body: &'static str) -> String {
+        let app = Router::new().route(
+            "/api/v3/query_sql",
+            post(move |Json(request): Json<Value>| async move {
+                assert_eq!(request["db"], "neptune");
+                assert_eq!(request["format"], "jsonl");
+                body
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    fn repository(host: String) -> SqlRepository {
+        SqlRepository::new(&InfluxSettings {
+            host,
+            token: "token".to_string(),
+            token_file: None,
+            database: "neptune".to_string(),
+            retention_policy: "autogen".to_string(),
+            backend: Backend::Sql,
+            org: None,
+            bucket: None,
+            connect_timeout_ms: 1_000,
+            request_timeout_ms: 1_000,
+            max_retries: 0,
+            retry_backoff_ms: 0,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_maps_rows_to_points() {
+        let host = stub_server(
+            "{\"time\":\"2025-01-01T00:01:00\",\"value\":78.2}\n\
+             {\"time\":\"2025-01-01T00:00:00\",\"value\":78.1}\n\
+             {\"time\":\"2025-01-01T00:02:00\"}\n",
+        )
+        .await;
+        let repo = repository(host);
+
+        let points = repo.query_time_series_downsampled("SELECT ...", 150, Downsampling::Average).await.unwrap();
+
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].time_ms, 1_735_689_600_000);
+        assert_eq!(points[1].value, 78.2);
+    }
+
+    #[tokio::test]
+    async fn test_maps_rows_to_series_metadata() {
+        // The stub answers every measurement's query with the same rows
+        let host = stub_server(
+            "{\"probe_type\":\"temp\",\"name\":\"Tmp\"}\n\
+             {\"probe_type\":\"ph\",\"name\":\"pH\"}\n\
+             {\"name\":\"ReturnPump\"}\n",
+        )
+        .await;
+        let repo = repository(host);
+
+        let series = repo.get_series_metadata("Planet_72", &TimeRange::Last { hours: 6 }).await.unwrap();
+
+        let probes: Vec<&SeriesMetadata> = series.iter().filter(|s| s.measurement == "apex_probe").collect();
+        assert_eq!(probes.len(), 2);
+        assert_eq!(probes[0].tag("probe_type"), Some("temp"));
+        assert_eq!(series.iter().filter(|s| s.measurement == "apex_cor").count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_groups_rows_by_tag_columns() {
+        let host = stub_server(
+            "{\"time\":\"2025-01-01T00:00:00\",\"name\":\"pH\",\"value\":8.1}\n\
+             {\"time\":\"2025-01-01T00:00:00\",\"name\":\"pHx3\",\"value\":8.3}\n\
+             {\"time\":\"2025-01-01T00:01:00\",\"name\":\"pH\",\"value\":8.2}\n",
+        )
+        .await;
+        let repo = repository(host);
+
+        let series = repo.query_tagged_series("SELECT ...", 150, Downsampling::Average).await.unwrap();
+
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].tag("name"), Some("pH"));
+        assert_eq!(series[0].points.len(), 2);
+        assert_eq!(series[1].points[0].value, 8.3);
+    }
+
+    #[test]
+    fn test_parse_rows_accepts_json_array() {
+        let rows = parse_rows("[{\"time\":1735689600000000000,\"pct\":42}]").unwrap();
+
+        assert_eq!(row_time_ms(&rows[0]), Some(1_735_689_600_000));
+        assert_eq!(row_value(&rows[0]), Some(42.0));
+    }
+}
@@ -4,41 +4,112 @@ mod application;
 mod infrastructure;
 mod presentation;
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{sync::Arc, time::Duration};
 use axum::{routing::get, Router};
 
 use crate::application::aquarium_service::AquariumService;
+use crate::application::caching_repository::{CacheTtls, CachingRepository};
+use crate::application::query_scheduler::QueryScheduler;
+use crate::application::single_flight::SingleFlightRepository;
 use crate::application::streaming_service::StreamingDashboardService;
-use crate::infrastructure::config::{load_influx_config, load_widgets_config};
+use crate::application::telemetry_repository::TelemetryRepository;
+use crate::infrastructure::cli::Options;
+use crate::infrastructure::config::{
+    load_influx_config, load_widgets_config, Backend, CacheSettings, SchedulerSettings,
+};
+use crate::infrastructure::config_check::{check_widgets_file, Severity};
+use crate::infrastructure::config_reload::{spawn_widgets_reload, ConfigHandle};
+use crate::infrastructure::fixture_repository::FixtureRepository;
+use crate::infrastructure::flux_repository::FluxRepository;
 use crate::infrastructure::influx_repository::InfluxRepository;
+use crate::infrastructure::sql_repository::SqlRepository;
 use crate::presentation::app_state::AppState;
-use crate::presentation::handlers::{health_check, list_aquariums, stream_dashboard};
+use crate::presentation::handlers::{
+    cache_stats, health_check, list_aquariums, list_pages, stream_dashboard, stream_dashboard_page,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
-    // Load configuration
-    let influx_config = load_influx_config()?;
-    let widgets_config = load_widgets_config()?;
+    // `validate-config [path]` checks a widgets file and exits
+    let mut args = std::env::args().skip(1).peekable();
+    let validate = args.next_if(|arg| arg == "validate-config").is_some();
+    let validate_path = args.next_if(|arg| validate && !arg.starts_with("--"));
+    let options = Options::parse(args, |name| std::env::var(name).ok())?;
+    if validate {
+        let path = validate_path.unwrap_or_else(|| options.widgets_path());
+        std::process::exit(validate_config(&path));
+    }
+
+    // Load configuration; widgets.toml is reloaded on change or SIGHUP
+    let widgets_path = options.widgets_path();
+    let widgets_config = Arc::new(ConfigHandle::new(load_widgets_config(&widgets_path)?));
+    spawn_widgets_reload(widgets_config.clone(), widgets_path)?;
 
     // Create repository (infrastructure layer)
-    let repository = Arc::new(InfluxRepository::new(
-        influx_config.influx.host,
-        influx_config.influx.token,
-        influx_config.influx.database,
-        influx_config.influx.retention_policy,
+    // `--fixtures <path>` (or `AQ_FIXTURES`) serves a recorded dataset instead of InfluxDB
+    // Fixture datasets answer the InfluxQL widget queries
+    let (backend, query_backend, scheduler_settings, cache_settings): (Arc<dyn TelemetryRepository>, _, _, _) =
+        match options.fixtures {
+            Some(path) => {
+                tracing::info!("Serving telemetry from fixture dataset {}", path);
+                let fixtures = FixtureRepository::load(&path)?;
+                (
+                    Arc::new(fixtures),
+                    Backend::InfluxQL,
+                    SchedulerSettings::default(),
+                    CacheSettings::default(),
+                )
+            }
+            None => {
+                let influx_config = load_influx_config(&options.config_dir, &options.influx)?;
+                let settings = &influx_config.influx;
+                let backend: Arc<dyn TelemetryRepository> = match settings.backend {
+                    Backend::InfluxQL => Arc::new(InfluxRepository::new(settings)?),
+                    Backend::Flux => Arc::new(FluxRepository::new(settings)?),
+                    Backend::Sql => Arc::new(SqlRepository::new(settings)?),
+                };
+                tracing::info!("Querying InfluxDB at {} using {:?}", settings.host, settings.backend);
+                (backend, settings.backend, influx_config.scheduler, influx_config.cache)
+            }
+        };
+
+    // Coalesce identical concurrent queries, and cache results in front of that. Each
+    // dashboard stream slots its scheduler in between, so only cache misses need a permit
+    let single_flight = Arc::new(SingleFlightRepository::new(backend));
+    let repository = Arc::new(CachingRepository::new(
+        single_flight,
+        CacheTtls {
+            single_value: Duration::from_secs(cache_settings.single_value_ttl_secs),
+            time_series: Duration::from_secs(cache_settings.time_series_ttl_secs),
+            series_metadata: Duration::from_secs(cache_settings.series_metadata_ttl_secs),
+            aquarium_ids: Duration::from_secs(cache_settings.aquarium_ids_ttl_secs),
+        },
+        cache_settings.max_bytes,
+    ));
+
+    // Create query scheduler shared by all dashboard streams
+    let scheduler = Arc::new(QueryScheduler::new(
+        scheduler_settings.max_in_flight,
+        scheduler_settings.max_per_stream,
     ));
 
     // Create services (application layer)
     let aquarium_service = AquariumService::new(repository.clone());
-    let streaming_service = StreamingDashboardService::new(repository.clone(), widgets_config);
+    let streaming_service = StreamingDashboardService::new(
+        repository.clone(),
+        scheduler,
+        widgets_config,
+        query_backend,
+    );
 
     // Create application state
     let state = Arc::new(AppState {
         aquarium_service,
         streaming_service,
+        cache: repository,
     });
 
     // Build router (presentation layer)
@@ -46,16 +117,36 @@ async fn main() -> anyhow::Result<()> {
     // so we don't use CompressionLayer to avoid double compression/decompression
     let router = Router::new()
         .route("/healthz", get(health_check))
+        .route("/cache/stats", get(cache_stats))
         .route("/aquariums", get(list_aquariums))
+        .route("/aquariums/:id/pages", get(list_pages))
         .route("/dashboards/:id", get(stream_dashboard))
+        .route("/dashboards/:id/:page", get(stream_dashboard_page))
         .with_state(state);
 
     // Start server
-    let addr: SocketAddr = "0.0.0.0:8080".parse().unwrap();
-    println!("Starting aquarium-telemetry service on {}", addr);
+    println!("Starting aquarium-telemetry service on {}", options.listen);
     
-    axum::serve(tokio::net::TcpListener::bind(addr).await?, router).await?;
+    axum::serve(tokio::net::TcpListener::bind(options.listen).await?, router).await?;
     
     Ok(())
 }
 
+/// Print every problem in the widgets file at `path`; the exit code is 1 on errors
+fn validate_config(path: &str) -> i32 {
+    let problems = match check_widgets_file(path) {
+        Ok((_, problems)) => problems,
+        Err(e) => {
+            eprintln!("{}: error: {:#}", path, e);
+            return 1;
+        }
+    };
+
+    for problem in &problems {
+        println!("{}", problem.render(path));
+    }
+    let errors = problems.iter().filter(|p| p.severity == Severity::Error).count();
+    println!("{}: {} errors, {} warnings", path, errors, problems.len() - errors);
+
+    if errors > 0 { 1 } else { 0 }
+}
@@ -1,11 +1,14 @@
 // Application state for HTTP handlers
 use crate::application::aquarium_service::AquariumService;
+use crate::application::caching_repository::CachingRepository;
 use crate::application::streaming_service::StreamingDashboardService;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub aquarium_service: AquariumService,
     pub streaming_service: StreamingDashboardService,
+    pub cache: Arc<CachingRepository>,
 }
 
 
@@ -1,19 +1,28 @@
 // HTTP request handlers
+use crate::domain::downsampling::point_budget;
+use crate::domain::time_range::TimeRange;
 use crate::infrastructure::chunked_thrift::stream_from_receiver;
 use crate::infrastructure::http_response::thrift_list_response;
 use crate::presentation::app_state::AppState;
 use axum::{
     extract::{Path, Query, State},
-    http::HeaderMap,
-    response::IntoResponse,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use telemetry_thrift::SDAquarium;
 
 #[derive(Deserialize)]
 pub struct RangeQuery {
     pub hours: Option<i32>,
+    /// Window start, RFC 3339 or epoch milliseconds (overrides `hours`)
+    pub start: Option<String>,
+    /// Window end, RFC 3339 or epoch milliseconds (defaults to now)
+    pub end: Option<String>,
+    /// Points per chart series, e.g. the chart's width in pixels
+    pub points: Option<usize>,
 }
 
 /// Health check endpoint
@@ -21,6 +30,15 @@ pub async fn health_check() -> &'static str {
     "ok"
 }
 
+/// Query cache counters (plain text)
+pub async fn cache_stats(State(state): State<Arc<AppState>>) -> String {
+    let stats = state.cache.stats();
+    format!(
+        "hits {}\nmisses {}\nentries {}\nbytes {}\n",
+        stats.hits, stats.misses, stats.entries, stats.bytes
+    )
+}
+
 /// List all aquariums
 pub async fn list_aquariums(
     headers: HeaderMap,
@@ -56,14 +74,73 @@ pub async fn list_aquariums(
     }
 }
 
+/// Page of a dashboard, as listed by `list_pages`
+#[derive(Serialize)]
+pub struct PageSummary {
+    pub id: String,
+    pub title: String,
+}
+
+/// List the dashboard pages of an aquarium (JSON)
+pub async fn list_pages(Path(id): Path<String>, State(state): State<Arc<AppState>>) -> Response {
+    if let Err(response) = check_aquarium(&state, &id).await {
+        return response;
+    }
+
+    let pages: Vec<PageSummary> = state
+        .streaming_service
+        .pages(&id)
+        .into_iter()
+        .map(|p| PageSummary {
+            id: p.id,
+            title: p.title,
+        })
+        .collect();
+    Json(pages).into_response()
+}
+
 /// Stream dashboard for a specific aquarium (progressive loading)
 pub async fn stream_dashboard(
     Path(id): Path<String>,
     Query(query): Query<RangeQuery>,
     headers: HeaderMap,
     State(state): State<Arc<AppState>>,
-) -> impl IntoResponse {
-    let hours = query.hours.unwrap_or(6);
+) -> Response {
+    stream(&state, &id, None, &query, &headers).await
+}
+
+/// Stream one page of an aquarium's dashboard
+pub async fn stream_dashboard_page(
+    Path((id, page)): Path<(String, String)>,
+    Query(query): Query<RangeQuery>,
+    headers: HeaderMap,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    stream(&state, &id, Some(&page), &query, &headers).await
+}
+
+async fn stream(
+    state: &AppState,
+    id: &str,
+    page: Option<&str>,
+    query: &RangeQuery,
+    headers: &HeaderMap,
+) -> Response {
+    if let Err(response) = check_aquarium(state, id).await {
+        return response;
+    }
+
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    let range = match TimeRange::from_params(
+        query.hours,
+        query.start.as_deref(),
+        query.end.as_deref(),
+        now_ms,
+    ) {
+        Ok(range) => range,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let points = point_budget(query.points);
 
     // Check if client accepts Brotli compression
     let compress = headers
@@ -72,7 +149,20 @@ pub async fn stream_dashboard(
         .map(|s| s.contains("br"))
         .unwrap_or(false);
 
-    let rx = state.streaming_service.stream_dashboard(&id, hours).await;
-    stream_from_receiver(rx, compress).await
+    let Some((rx, guard)) = state.streaming_service.stream_dashboard(id, page, &range, points).await else {
+        return (StatusCode::NOT_FOUND, "Unknown page").into_response();
+    };
+    stream_from_receiver(rx, guard, compress).await.into_response()
 }
 
+/// Only known aquariums get queried; the id ends up inside every widget query
+async fn check_aquarium(state: &AppState, id: &str) -> Result<(), Response> {
+    match state.aquarium_service.is_known(id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "Unknown aquarium").into_response()),
+        Err(e) => {
+            tracing::error!("Error fetching aquariums: {:#}", e);
+            Err(StatusCode::SERVICE_UNAVAILABLE.into_response())
+        }
+    }
+}